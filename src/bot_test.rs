@@ -0,0 +1,244 @@
+use std::sync::mpsc::channel;
+use irc::client::prelude::Message;
+use cli::{Args, Line, Mode};
+use fakeirc::FakeIrc;
+use model::*;
+use rng::Rng;
+use transcript::Transcript;
+use super::{connection_change, process_text, replay};
+
+/// Test reading the command line.
+#[test]
+fn test_args() {
+    let parse = |s: &str| {
+        let v = s.split_whitespace().map(|w| w.to_string()).collect::<Vec<_>>();
+        Args::parse(&v)
+    };
+    let a = parse("").unwrap();
+    assert!(a.config == "pravda.json" && a.channel.is_none() && !a.dry_run);
+    assert!(a.mode == Mode::Run);
+    let a = parse("--config bot.json --channel gulag --dry-run run").unwrap();
+    assert!(a.config == "bot.json" && a.dry_run);
+    assert!(a.channel == Some("#gulag".to_string()));
+    assert!(parse("console").unwrap().mode == Mode::Console);
+    assert!(parse("replay game.txt").unwrap().mode == Mode::Replay("game.txt".to_string()));
+    assert!(parse("validate-config --config x.json").unwrap().mode == Mode::ValidateConfig);
+    assert!(parse("--help replay").unwrap().mode == Mode::Help);
+    assert!(parse("replay").is_err());
+    assert!(parse("--config").is_err());
+    assert!(parse("--loud").is_err());
+    assert!(parse("console run").is_err());
+}
+
+/// Test reading lines of console sessions and transcripts.
+#[test]
+fn test_transcript_lines() {
+    let say = Line::Say("ivan".to_string(), "!denounce olga".to_string());
+    assert!(Line::parse("ivan: !denounce olga") == Ok(say));
+    let tell = Line::Tell("olga".to_string(), "!kill ivan".to_string());
+    assert!(Line::parse("  olga> !kill ivan") == Ok(tell));
+    assert!(Line::parse("leave ivan") == Ok(Line::Leave("ivan".to_string())));
+    let rename = Line::Rename("ivan".to_string(), "ivan_away".to_string());
+    assert!(Line::parse("nick ivan ivan_away") == Ok(rename));
+    assert!(Line::parse("tick") == Ok(Line::Tick(1)));
+    assert!(Line::parse("tick 30") == Ok(Line::Tick(30)));
+    assert!(Line::parse("seed 7") == Ok(Line::Seed(7)));
+    assert!(Line::parse("# night one") == Ok(Line::Nothing));
+    assert!(Line::parse("") == Ok(Line::Nothing));
+    assert!(Line::parse("tick soon").is_err());
+    assert!(Line::parse("ivan says hello: there").is_err());
+    let say = Line::Say("kim!k@2001:db8::1".to_string(), "!extend".to_string());
+    assert!(Line::parse("kim!k@2001:db8::1: !extend") == Ok(say));
+    assert!(Line::parse("ivan:me@mail.ru") ==
+            Ok(Line::Say("ivan".to_string(), "me@mail.ru".to_string())));
+    // Lines are written as they are read.
+    for l in ["ivan: !denounce olga", "olga> !kill ivan", "leave ivan", "nick ivan ivan_away",
+              "tick", "tick 30", "seed 7", "ivan: ", "kim: tick: 3 > 2",
+              "kim!k@kremlin.su: !extend 30", "kim!k@2001:db8::1> !status",
+              "kim!k@2001:db8::1: ", "kim!k@h: a: b> c"]
+        .iter() {
+        assert!(Line::parse(l).unwrap().to_string() == *l);
+    }
+}
+
+/// A fake server with a bot whose game deals from a fixed seed.
+fn fake_irc() -> FakeIrc {
+    let mut g = Game::new("#test_channel");
+    g.rng = Rng::new(1);
+    FakeIrc::new(g)
+}
+
+/// Test a game over IRC, from joining to the first day: who hears what.
+#[test]
+fn test_irc_game_start() {
+    let mut irc = fake_irc();
+    let nicks = ["ivan", "olga", "kim", "lev", "masha"];
+    for n in nicks.iter() {
+        irc.join(n);
+        irc.say(n, "!join");
+    }
+    assert!(irc.sent_to("#test_channel")[0] == "ivan starting new game!");
+    assert!(irc.sent_to("#test_channel")[4] == "masha joins the game.");
+    irc.take();
+    irc.tick_phase();
+    let channel = irc.sent_to("#test_channel");
+    assert!(channel[0] == "Attention, comrades: 30 seconds left.");
+    assert!(channel[2].starts_with("The Revolution calls! 5 comrades take part"));
+    // Each player hears their own role, and only theirs.
+    for n in nicks.iter() {
+        let heard = irc.sent_to(n);
+        let g = irc.game.as_ref().unwrap();
+        let role = g.player(n).unwrap().role;
+        assert!(heard.len() == 1);
+        assert!(heard[0].contains(&g.theme.get(role.key())));
+    }
+    assert!(irc.take().iter().all(|l| l.starts_with("PRIVMSG ")));
+    // Night actions belong in private, denunciations in public.
+    irc.say("ivan", "!kill olga");
+    irc.tell("olga", "!denounce ivan");
+    assert!(irc.sent_to("ivan") == vec!["This command must be sent to me privately."]);
+    assert!(irc.sent_to("olga") == vec!["This command must be issued in public."]);
+}
+
+/// Test that parting and quitting leave the game, and changing nick does not.
+#[test]
+fn test_irc_leaving() {
+    let mut irc = fake_irc();
+    for n in ["ivan", "olga", "kim", "lev"].iter() {
+        irc.say(n, "!join");
+    }
+    irc.take();
+    irc.part("ivan");
+    irc.quit("olga");
+    irc.nick("kim", "kim_away");
+    assert!(irc.sent_to("#test_channel") ==
+            vec!["ivan has left the game.",
+                 "olga has left the game.",
+                 "By decree, comrade kim is henceforth known as kim_away."]);
+    match irc.game.as_ref().unwrap().players {
+        Participants::Joiners(ref v) => assert!(*v == vec!["kim_away", "lev"]),
+        Participants::Players(_) => unreachable!(),
+    }
+}
+
+/// Test that the owner's quit command suspends a game and leaves the server.
+#[test]
+fn test_irc_quit() {
+    let mut irc = fake_irc();
+    irc.say("ivan", "!join");
+    irc.take();
+    irc.tell("owner", "exitnow");
+    assert!(irc.take() ==
+            vec!["PRIVMSG #test_channel :The game is suspended by order of the Politburo. \
+                  Nobody is to leave the room.",
+                 "PRIVMSG #test_channel :Owner asked me to quit, do vstrechi!",
+                 "QUIT :Pravda goes bye-bye!"]);
+    assert!(irc.game.is_none());
+    irc.say("ivan", "!join");
+    assert!(irc.take().is_empty());
+}
+
+/// Test that the game's clock stands still while the bot is disconnected,
+/// and runs again once it is back.
+#[test]
+fn test_irc_clock_paused() {
+    let mut irc = fake_irc();
+    for n in ["ivan", "olga", "kim", "lev", "masha"].iter() {
+        irc.say(n, "!join");
+    }
+    irc.tick_phase();
+    irc.tick(5);
+    let remaining = irc.game.as_ref().unwrap().remaining();
+    irc.line("ERROR :Closing Link: pravdabot (Ping timeout)");
+    assert!(!irc.is_connected());
+    irc.take();
+    irc.tick(600);
+    assert!(irc.take().is_empty());
+    irc.line(":irc.fake 376 pravdabot :End of /MOTD command.");
+    assert!(irc.is_connected());
+    assert!(irc.game.as_ref().unwrap().remaining() == remaining);
+    irc.tick(1);
+    assert!(irc.game.as_ref().unwrap().remaining() == remaining - 1);
+}
+
+/// Test which server messages tell the bot it is connected, or no longer is.
+#[test]
+fn test_connection_change() {
+    let change = |raw: &str| {
+        let msg = raw.parse::<Message>().unwrap();
+        connection_change(&msg, "pravdabot", "#test_channel")
+    };
+    assert!(change(":irc.fake 376 pravdabot :End of /MOTD command.") == Some(true));
+    assert!(change(":irc.fake 422 pravdabot :MOTD File is missing") == Some(true));
+    assert!(change(":pravdabot!bot@fake JOIN #Test_Channel") == Some(true));
+    assert!(change(":pravdabot!bot@fake JOIN #elsewhere").is_none());
+    assert!(change(":ivan!ivan@fake JOIN #test_channel").is_none());
+    assert!(change("ERROR :Closing Link: pravdabot (Ping timeout)") == Some(false));
+    assert!(change(":ivan!ivan@fake PRIVMSG #test_channel :!join").is_none());
+}
+
+/// Test that replaying the transcript of a session plays the same game.
+#[test]
+fn test_irc_transcript() {
+    let path = std::env::temp_dir().join("pravdabot_test.transcript");
+    let path = path.to_str().unwrap();
+    let mut g = Game::new("#test_channel");
+    g.rng = Rng::new(42);
+    g.settings.set("admins", "kim!*@fake").unwrap();
+    let mut irc = FakeIrc::keeping(g, Transcript::create(path, 42).unwrap());
+    for n in ["ivan", "olga", "kim", "lev", "masha"].iter() {
+        irc.join(n);
+        irc.say(n, "!join");
+    }
+    irc.tick_phase();
+    irc.tick(3);
+    irc.say("ivan", "!denounce olga");
+    irc.tell("olga", "!will It was kim.");
+    irc.say("kim", "!extend 100");
+    irc.part("masha");
+    irc.tick(2);
+    assert!(irc.sent_to("#test_channel").iter().any(|l| l.starts_with("By order of kim")));
+    irc.link.transcript.lock().unwrap().flush();
+    let lines = std::fs::read_to_string(path).unwrap();
+    let lines = lines.lines().collect::<Vec<_>>();
+    assert!(lines[..3] == ["seed 42", "ivan!ivan@fake: !join", "olga!olga@fake: !join"]);
+    // The ticks of the starting phase and the next three seconds make one line.
+    assert!(lines[lines.len() - 6].starts_with("tick "));
+    assert!(lines[lines.len() - 5..] ==
+            ["ivan!ivan@fake: !denounce olga",
+             "olga!olga@fake> !will It was kim.",
+             "kim!kim@fake: !extend 100",
+             "leave masha",
+             "tick 2"]);
+    let live = irc.game.take().unwrap();
+    let mut g = Game::new("#test_channel");
+    g.settings.set("admins", "kim!*@fake").unwrap();
+    let replayed = replay(g, path).unwrap();
+    let _ = std::fs::remove_file(path);
+    assert!(replayed.phase == live.phase && replayed.remaining() == live.remaining());
+    assert!(replayed.snapshot() == live.snapshot());
+}
+
+/// Test reading the time given to !extend and !shorten: half a minute unless
+/// a number of seconds that fits is given.
+#[test]
+fn test_extend_args() {
+    let (tx, rx) = channel();
+    let secs = |text: &str| {
+        process_text("kim!kim@kremlin.su", "#test_channel", text, &tx);
+        match rx.try_recv() {
+            Ok(GameEvent::Extend(ref nick, ref mask, secs)) => {
+                assert!(nick == "kim" && mask == "kim!kim@kremlin.su");
+                secs
+            }
+            _ => unreachable!(),
+        }
+    };
+    assert!(secs("!extend") == 30 && secs("!shorten") == -30);
+    assert!(secs("!extend 90") == 90 && secs("!SHORTEN 45") == -45);
+    assert!(secs("!extend 4294967295") == 4294967295);
+    assert!(secs("!shorten 4294967295") == -4294967295);
+    assert!(secs("!extend 99999999999999999999") == 30);
+    assert!(secs("!shorten -9223372036854775808") == -30);
+    assert!(secs("!extend -60") == 30);
+}
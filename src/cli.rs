@@ -0,0 +1,184 @@
+//! Module: cli.
+//! This module contains the command line: its options and subcommands, and
+//! the lines of offline console sessions and replayed game transcripts.
+
+use std::fmt;
+
+/// Configuration file read when none is given.
+pub const DEFAULT_CONFIG: &str = "pravda.json";
+
+/// Help text.
+pub const USAGE: &str = "\
+Usage: pravdabot [options] [command]
+
+Commands:
+  run                 Connect to IRC and host games (the default).
+  console             Play an offline game on the terminal.
+  replay <transcript> Replay a recorded game transcript offline.
+  validate-config     Check the configuration, then exit.
+
+Options:
+  --config <path>     Configuration file to read (default: pravda.json).
+  --channel <name>    Channel to play in, instead of the configured one.
+  --dry-run           Connect, but only log outgoing messages instead of sending them.
+  --help              Show this help.
+
+Console and transcript lines:
+  <nick>: <text>      <nick> says <text> in the channel.
+  <nick>> <text>      <nick> sends <text> to the bot privately.
+                      Either can be sent from <nick>!<user>@<host> instead,
+                      which admin commands need.
+  leave <nick>        <nick> leaves the channel.
+  nick <nick> <new>   <nick> changes nick to <new>.
+  tick [n]            One second passes, or n.
+  seed <n>            Deal from a fixed random seed (transcripts only).
+  # ...               A comment.";
+
+/// What the bot was asked to do.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Mode {
+    Run,
+    Console,
+    Replay(String),
+    ValidateConfig,
+    Help,
+}
+
+/// Parsed command line.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Args {
+    pub config: String,
+    pub channel: Option<String>,
+    pub dry_run: bool,
+    pub mode: Mode,
+}
+
+impl Args {
+    /// Parse the command line arguments, without the program name.
+    pub fn parse(args: &[String]) -> Result<Args, String> {
+        let mut a = Args {
+            config: DEFAULT_CONFIG.to_string(),
+            channel: None,
+            dry_run: false,
+            mode: Mode::Run,
+        };
+        let mut command = Vec::new();
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            match arg.as_str() {
+                "--config" => {
+                    a.config = it.next().ok_or("--config needs a path.".to_string())?.clone();
+                }
+                "--channel" => {
+                    let c = it.next().ok_or("--channel needs a name.".to_string())?;
+                    a.channel = Some(match c.starts_with('#') {
+                        true => c.clone(),
+                        false => format!("#{}", c),
+                    });
+                }
+                "--dry-run" => a.dry_run = true,
+                "--help" | "-h" => a.mode = Mode::Help,
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ => command.push(arg.as_str()),
+            }
+        }
+        if a.mode == Mode::Help {
+            return Ok(a);
+        }
+        a.mode = match command.as_slice() {
+            [] | ["run"] => Mode::Run,
+            ["console"] => Mode::Console,
+            ["replay", path] => Mode::Replay(path.to_string()),
+            ["replay"] => return Err("replay needs a transcript.".to_string()),
+            ["validate-config"] => Mode::ValidateConfig,
+            _ => return Err(format!("Unknown command: {}", command.join(" "))),
+        };
+        return Ok(a);
+    }
+}
+
+/// A line of a console session or game transcript.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Line {
+    /// A nick, or a nick!user@host, says something in the channel.
+    Say(String, String),
+    /// A nick, or a nick!user@host, sends something to the bot privately.
+    Tell(String, String),
+    Leave(String),
+    /// A nick changes to another.
+    Rename(String, String),
+    Tick(u32),
+    Seed(u64),
+    /// A blank line or a comment.
+    Nothing,
+}
+
+impl Line {
+    /// Parse a line.
+    pub fn parse(line: &str) -> Result<Line, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(Line::Nothing);
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["tick"] => return Ok(Line::Tick(1)),
+            ["tick", n] => {
+                return n.parse().map(Line::Tick).map_err(|_| format!("Not a number: {}", n))
+            }
+            ["seed", n] => {
+                return n.parse().map(Line::Seed).map_err(|_| format!("Not a seed: {}", n))
+            }
+            ["leave", nick] => return Ok(Line::Leave(nick.to_string())),
+            ["nick", nick, new] => return Ok(Line::Rename(nick.to_string(), new.to_string())),
+            _ => (),
+        }
+        match source_end(line) {
+            Some(i) if i > 0 && !line[..i].contains(char::is_whitespace) => {
+                let nick = line[..i].to_string();
+                let text = line[i + 1..].trim().to_string();
+                match &line[i..i + 1] {
+                    ":" => Ok(Line::Say(nick, text)),
+                    _ => Ok(Line::Tell(nick, text)),
+                }
+            }
+            _ => Err(format!("Not a line of play: {}", line)),
+        }
+    }
+}
+
+/// Where the sender at the start of a line of play ends. A nick ends at the
+/// first ':' or '>'. A host may hold colons, so a nick!user@host ends at the
+/// first one followed by a space, or at the end of the line.
+fn source_end(line: &str) -> Option<usize> {
+    let at = match line.find('@') {
+        Some(at) if line[..at].contains('!') && !line[..at].contains([' ', '\t', ':', '>']) => at,
+        _ => return line.find([':', '>']),
+    };
+    let rest = &line[at..];
+    let end = match (rest.find(": "), rest.find("> ")) {
+        (Some(i), Some(j)) => Some(i.min(j)),
+        (i, j) => i.or(j),
+    };
+    match end {
+        Some(i) => Some(at + i),
+        None if rest.ends_with([':', '>']) => Some(line.len() - 1),
+        None => None,
+    }
+}
+
+impl fmt::Display for Line {
+    /// The line as it is written in a transcript, to be parsed back.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Line::Say(ref source, ref text) => write!(f, "{}: {}", source, text),
+            Line::Tell(ref source, ref text) => write!(f, "{}> {}", source, text),
+            Line::Leave(ref nick) => write!(f, "leave {}", nick),
+            Line::Rename(ref nick, ref new) => write!(f, "nick {} {}", nick, new),
+            Line::Tick(1) => write!(f, "tick"),
+            Line::Tick(n) => write!(f, "tick {}", n),
+            Line::Seed(n) => write!(f, "seed {}", n),
+            Line::Nothing => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Module: clock.
+//! This module contains the clock games read the time from. The bot, tests
+//! and replays use a manual clock that only moves a second at each tick, so
+//! that time stands still while the bot is disconnected, and whole phases can
+//! be played without waiting for them.
+
+use std::time::{Duration, Instant};
+
+/// Where a game reads the time from.
+#[derive(Clone, Debug)]
+pub enum Clock {
+    /// The system's monotonic clock, counted from an instant.
+    System(Instant),
+    /// A clock standing at a time, until it is advanced.
+    Manual(Duration),
+}
+
+impl Clock {
+    /// A clock that runs on its own, from now.
+    pub fn system() -> Clock {
+        Clock::System(Instant::now())
+    }
+
+    /// A clock that stands still at zero until advanced.
+    pub fn manual() -> Clock {
+        Clock::Manual(Duration::from_secs(0))
+    }
+
+    /// Time since the clock started.
+    pub fn now(&self) -> Duration {
+        match *self {
+            Clock::System(start) => start.elapsed(),
+            Clock::Manual(t) => t,
+        }
+    }
+
+    /// Move a manual clock forward. The system clock moves on its own.
+    pub fn advance(&mut self, by: Duration) {
+        if let Clock::Manual(ref mut t) = *self {
+            *t += by;
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Module: fakeirc.
+//! A fake IRC server for tests. It feeds the bot the lines a server would
+//! send, through the same process_cmd and deliver as a live connection, and
+//! keeps every line the bot sends back, without any network.
+
+use std::cell::RefCell;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use irc::client::prelude::Message;
+use clock::Clock;
+use model::{Game, GameEvent, Phase};
+use transcript::Transcript;
+use super::{handle_event, receive, send_tick, shut_down, Link, Transport};
+
+/// Nick the bot goes by on the fake server.
+pub const BOT_NICK: &str = "pravdabot";
+
+/// The lines the bot sends, as IRC commands.
+#[derive(Default)]
+pub struct Captured {
+    lines: RefCell<Vec<String>>,
+}
+
+impl Transport for Captured {
+    fn privmsg(&self, target: &str, text: &str) {
+        self.lines.borrow_mut().push(format!("PRIVMSG {} :{}", target, text));
+    }
+
+    fn flush(&self) {}
+
+    fn quit(&self, text: &str) {
+        self.lines.borrow_mut().push(format!("QUIT :{}", text));
+    }
+}
+
+/// A scripted server with the bot connected to it, in its game's channel.
+pub struct FakeIrc {
+    /// The bot's game; None once the bot has quit.
+    pub game: Option<Game>,
+    pub out: Captured,
+    /// The bot's connection, as it tells from what the server sent.
+    pub link: Link,
+    tx: Sender<GameEvent>,
+    rx: Receiver<GameEvent>,
+}
+
+impl FakeIrc {
+    /// Connect a game to the server. Its clock only moves on ticks.
+    pub fn new(game: Game) -> FakeIrc {
+        FakeIrc::keeping(game, Transcript::none())
+    }
+
+    /// Connect a game to the server, keeping a transcript of what the bot hears.
+    pub fn keeping(mut game: Game, transcript: Transcript) -> FakeIrc {
+        game.clock = Clock::manual();
+        let (tx, rx) = channel();
+        let link = Link::new(transcript);
+        link.connected.store(true, Ordering::SeqCst);
+        let f = FakeIrc {
+            game: Some(game),
+            out: Captured::default(),
+            link: link,
+            tx: tx,
+            rx: rx,
+        };
+        return f;
+    }
+
+    /// The game's channel.
+    pub fn channel(&self) -> String {
+        self.game.as_ref().map_or(String::new(), |g| g.channel.clone())
+    }
+
+    /// Send the bot a raw line from the server, and let it answer.
+    pub fn line(&mut self, raw: &str) {
+        let msg = raw.parse::<Message>().unwrap();
+        let channel = self.channel();
+        receive(msg, BOT_NICK, &channel, &self.link, &self.tx);
+        self.pump();
+    }
+
+    /// A nick joins the channel.
+    pub fn join(&mut self, nick: &str) {
+        let raw = format!(":{0}!{0}@fake JOIN {1}", nick, self.channel());
+        self.line(&raw);
+    }
+
+    /// A nick says something in the channel.
+    pub fn say(&mut self, nick: &str, text: &str) {
+        let raw = format!(":{0}!{0}@fake PRIVMSG {1} :{2}", nick, self.channel(), text);
+        self.line(&raw);
+    }
+
+    /// A nick sends the bot a private message.
+    pub fn tell(&mut self, nick: &str, text: &str) {
+        self.line(&format!(":{0}!{0}@fake PRIVMSG {1} :{2}", nick, BOT_NICK, text));
+    }
+
+    /// A nick leaves the channel.
+    pub fn part(&mut self, nick: &str) {
+        let raw = format!(":{0}!{0}@fake PART {1}", nick, self.channel());
+        self.line(&raw);
+    }
+
+    /// A nick disconnects.
+    pub fn quit(&mut self, nick: &str) {
+        self.line(&format!(":{0}!{0}@fake QUIT :Connection reset by peer", nick));
+    }
+
+    /// A nick changes to another.
+    pub fn nick(&mut self, nick: &str, new: &str) {
+        self.line(&format!(":{0}!{0}@fake NICK {1}", nick, new));
+    }
+
+    /// Let some seconds pass. The bot gets a tick for each, while connected.
+    pub fn tick(&mut self, secs: u32) {
+        for _ in 0..secs {
+            send_tick(&self.link, &self.tx);
+            self.pump();
+        }
+    }
+
+    /// Whether the bot takes itself to be connected.
+    pub fn is_connected(&self) -> bool {
+        self.link.connected.load(Ordering::SeqCst)
+    }
+
+    /// Tick until the game is in another phase.
+    pub fn tick_phase(&mut self) {
+        let phase = self.game.as_ref().map(|g| g.phase.clone());
+        if phase.is_none() || phase == Some(Phase::Inactive) {
+            return;
+        }
+        while self.game.as_ref().map(|g| g.phase.clone()) == phase {
+            self.tick(1);
+        }
+    }
+
+    /// Handle everything queued, as the bot's main loop would.
+    fn pump(&mut self) {
+        while let Ok(event) = self.rx.try_recv() {
+            let game = match self.game.take() {
+                Some(g) => g,
+                None => return,
+            };
+            match event {
+                GameEvent::Quit => shut_down(&game, &self.rx, &self.out, None),
+                event => self.game = Some(handle_event(game, event, &self.out, &self.tx)),
+            }
+        }
+    }
+
+    /// Every line the bot sent, oldest first, forgetting them.
+    pub fn take(&mut self) -> Vec<String> {
+        self.out.lines.borrow_mut().drain(..).collect()
+    }
+
+    /// What the bot sent to a channel or nick, oldest first.
+    pub fn sent_to(&self, target: &str) -> Vec<String> {
+        let prefix = format!("PRIVMSG {} :", target);
+        self.out
+            .lines
+            .borrow()
+            .iter()
+            .filter(|l| l.starts_with(&prefix))
+            .map(|l| l[prefix.len()..].to_string())
+            .collect()
+    }
+}
@@ -0,0 +1,61 @@
+//! Pravda, the game engine of Pravdabot: a 1930s Soviet themed mafia game.
+//!
+//! The engine knows nothing of IRC. A `Game` is bound to a channel name, and
+//! moves on by being handed `GameEvent`s: players joining, leaving and giving
+//! commands, and a `Tick` every second so that timed phases can run out.
+//! Everything the game has to say comes back as `GameReaction`s in
+//! `Game::pending`, each made of `GameMessage`s for the channel or for some
+//! players (`Recipients`). Once they are sent, `Game::clean_up` moves them to
+//! the game's log.
+//!
+//! ```
+//! extern crate pravda;
+//! use pravda::{Game, GameEvent, Recipients};
+//!
+//! let mut game = Game::new("#pravda");
+//! game = game.process(GameEvent::Join("ivan".to_string()));
+//! for reaction in game.pending.iter() {
+//!     for m in reaction.msg.iter() {
+//!         match m.recipients {
+//!             Recipients::Channel(ref c) => println!("{}: {}", c, m.content),
+//!             Recipients::Nicks(ref v) => println!("{}: {}", v.join(","), m.content),
+//!         }
+//!     }
+//! }
+//! game = game.clean_up();
+//! assert!(game.pending.is_empty());
+//! ```
+//!
+//! How a game plays is set by its `Setup` (the roles dealt), its `Settings`
+//! (timings and rules) and its `Theme` (the wording). Its `Clock` can be
+//! swapped for a manual one, to play phases out without waiting for them.
+//!
+//! Everything on `Game` holds every secret. What a player or a spectator may
+//! be shown is their `View` of it, from `Game::view_for` or `Game::public_view`.
+
+// The code spells out its returns and field inits.
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+
+pub mod clock;
+pub mod logging;
+pub mod model;
+pub mod night;
+pub mod report;
+pub mod rng;
+pub mod settings;
+pub mod setup;
+pub mod stats;
+pub mod theme;
+pub mod view;
+
+#[cfg(test)]
+mod test;
+
+pub use clock::Clock;
+pub use model::{Ballot, DeathCause, Game, GameEvent, GameMessage, GameReaction, Participants,
+                Phase, Player, Recipients, Role, Team};
+pub use night::ActionKind;
+pub use settings::{RelayPolicy, Settings, TieBreak};
+pub use setup::{Reveal, Setup};
+pub use theme::Theme;
+pub use view::{Seen, View};
@@ -0,0 +1,281 @@
+//! Module: logging.
+//! This module contains the bot's leveled logging. Each record is one line of
+//! "key=value" fields: the time, the level, the context (channel, game id,
+//! phase and nick, when known) and the message. Records go to standard error
+//! and/or a log file that is rotated when it grows too large.
+//!
+//! Private game information, such as role assignments, night actions and
+//! private messages, is only ever logged at the trace level, so operators do
+//! not read it by accident at the default level.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default size, in bytes, a log file may reach before it is rotated.
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+/// Default number of rotated log files kept besides the current one.
+const DEFAULT_KEEP: u8 = 3;
+
+/// The logger records are sent to, once set up by init.
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+/// How important a record is. Trace records may carry private game information.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// Level for a name as written in the configuration, ignoring case.
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name.to_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// Name of the level in records.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// What a record is about. Unknown fields are left out of the record.
+#[derive(Clone, Default, Debug)]
+pub struct Context {
+    pub channel: Option<String>,
+    pub game: Option<u32>,
+    pub phase: Option<String>,
+    pub nick: Option<String>,
+}
+
+impl Context {
+    /// A context with only a nick.
+    pub fn nick(nick: &str) -> Context {
+        let c = Context { nick: Some(nick.to_string()), ..Context::default() };
+        return c;
+    }
+
+    /// The context fields, as "key=value" pairs.
+    fn fields(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(ref c) = self.channel {
+            fields.push(format!("channel={}", quote(c)));
+        }
+        if let Some(g) = self.game {
+            fields.push(format!("game={}", g));
+        }
+        if let Some(ref p) = self.phase {
+            fields.push(format!("phase={}", quote(p)));
+        }
+        if let Some(ref n) = self.nick {
+            fields.push(format!("nick={}", quote(n)));
+        }
+        return fields.join(" ");
+    }
+}
+
+/// Quote a field value if it has spaces, quotes or is empty.
+fn quote(value: &str) -> String {
+    match value.is_empty() || value.contains([' ', '"', '=']) {
+        true => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => value.to_string(),
+    }
+}
+
+/// A log file that is moved aside when it grows too large: "bot.log" becomes
+/// "bot.log.1", "bot.log.1" becomes "bot.log.2", and so on.
+pub struct RotatingFile {
+    path: String,
+    max_size: u64,
+    keep: u8,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Open a log file for appending.
+    pub fn open(path: &str, max_size: u64, keep: u8) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        let f = RotatingFile {
+            path: path.to_string(),
+            max_size: max_size,
+            keep: keep,
+            file: file,
+            size: size,
+        };
+        return Ok(f);
+    }
+
+    /// Append a line, rotating the file first if it would grow too large.
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        return Ok(());
+    }
+
+    /// Move the current file and older ones aside, dropping the oldest.
+    fn rotate(&mut self) -> io::Result<()> {
+        let name = |n: u8| format!("{}.{}", self.path, n);
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                if fs::metadata(name(n)).is_ok() {
+                    fs::rename(name(n), name(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, name(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        return Ok(());
+    }
+}
+
+/// Where records go, and from which level.
+pub struct Logger {
+    pub level: Level,
+    pub stderr: bool,
+    pub file: Option<RotatingFile>,
+}
+
+impl Logger {
+    /// Read logging options out of the bot's configuration options:
+    /// log_level, log_stderr, log_file, log_max_size and log_keep.
+    pub fn from_options(opts: &HashMap<String, String>) -> Result<Logger, String> {
+        let mut l = Logger::default();
+        if let Some(v) = opts.get("log_level") {
+            l.level = Level::from_name(v).ok_or(format!("Unknown log level: {}", v))?;
+        }
+        if let Some(v) = opts.get("log_stderr") {
+            l.stderr = match v.as_str() {
+                "true" | "yes" | "on" => true,
+                "false" | "no" | "off" => false,
+                _ => return Err(format!("log_stderr must be true or false, not {}", v)),
+            };
+        }
+        let max_size = match opts.get("log_max_size") {
+            Some(v) => v.parse().map_err(|_| format!("Not a log file size: {}", v))?,
+            None => DEFAULT_MAX_SIZE,
+        };
+        let keep = match opts.get("log_keep") {
+            Some(v) => v.parse().map_err(|_| format!("Not a number of log files: {}", v))?,
+            None => DEFAULT_KEEP,
+        };
+        if let Some(path) = opts.get("log_file") {
+            l.file = Some(RotatingFile::open(path, max_size, keep)
+                .map_err(|e| format!("{}: {}", path, e))?);
+        }
+        return Ok(l);
+    }
+
+    /// Write a record, if its level is enabled.
+    pub fn log(&mut self, level: Level, ctx: &Context, msg: &str) {
+        if level > self.level {
+            return;
+        }
+        let line = format_record(level, ctx, msg);
+        if self.stderr {
+            eprintln!("{}", line);
+        }
+        if let Some(ref mut f) = self.file {
+            if let Err(e) = f.write_line(&line) {
+                eprintln!("Cannot write to the log file: {}", e);
+            }
+        }
+    }
+}
+
+impl Default for Logger {
+    /// Records from the info level up, to standard error.
+    fn default() -> Logger {
+        Logger {
+            level: Level::Info,
+            stderr: true,
+            file: None,
+        }
+    }
+}
+
+/// A record as a line of "key=value" fields.
+pub fn format_record(level: Level, ctx: &Context, msg: &str) -> String {
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    let fields = ctx.fields();
+    let mut line = format!("time={} level={}", time, level.name());
+    if !fields.is_empty() {
+        line.push(' ');
+        line.push_str(&fields);
+    }
+    line.push_str(&format!(" msg={}", quote(msg)));
+    return line;
+}
+
+/// Send records to a logger from now on.
+pub fn init(logger: Logger) {
+    if let Ok(mut l) = LOGGER.lock() {
+        *l = Some(logger);
+    }
+}
+
+/// Write a record. Until init is called, records go to the default logger.
+pub fn log(level: Level, ctx: &Context, msg: &str) {
+    if let Ok(mut l) = LOGGER.lock() {
+        if l.is_none() {
+            *l = Some(Logger::default());
+        }
+        if let Some(ref mut logger) = *l {
+            logger.log(level, ctx, msg);
+        }
+    }
+}
+
+/// Write an error record.
+pub fn error(ctx: &Context, msg: &str) {
+    log(Level::Error, ctx, msg);
+}
+
+/// Write a warning record.
+pub fn warn(ctx: &Context, msg: &str) {
+    log(Level::Warn, ctx, msg);
+}
+
+/// Write an info record.
+pub fn info(ctx: &Context, msg: &str) {
+    log(Level::Info, ctx, msg);
+}
+
+/// Write a debug record.
+pub fn debug(ctx: &Context, msg: &str) {
+    log(Level::Debug, ctx, msg);
+}
+
+/// Write a trace record. Only trace records may carry private game information.
+pub fn trace(ctx: &Context, msg: &str) {
+    log(Level::Trace, ctx, msg);
+}
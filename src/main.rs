@@ -1,82 +1,328 @@
-//! Pravdabot, a 1930s Soviet themed mafia game IRC bot.
+//! Pravdabot, a 1930s Soviet themed mafia game IRC bot, on top of the
+//! pravda game engine.
+
+// The code spells out its returns and field inits.
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
 
 extern crate irc;
 use irc::client::prelude::*;
 use irc::client::data::command::Command;
+use irc::client::data::response::Response;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read};
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::Duration;
-use std::sync::mpsc::{channel, Sender};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+extern crate pravda;
+use pravda::{clock, logging, model, night, rng, settings, setup, stats, theme};
 
 #[cfg(test)]
-mod test;
+mod bot_test;
+#[cfg(test)]
+mod fakeirc;
 
-pub mod model;
+pub mod cli;
+use cli::{Args, Line, Mode};
+use clock::Clock;
+use logging::{Context, Logger};
 use model::*;
+use night::ActionKind;
+use rng::Rng;
+use settings::Settings;
+use setup::Setup;
+pub mod signals;
+use stats::Stats;
+use theme::Theme;
+pub mod transcript;
+use transcript::Transcript;
 
-/// Function to send messages.
-/// It takes a target string, a message string, and an IrcServer object.
-pub fn send(c: &String, m: &String, s: IrcServer) {
-    s.send_privmsg(c, m).unwrap();
+/// Where the bot's messages go out to.
+pub trait Transport {
+    /// Send a message to a channel or a nick.
+    fn privmsg(&self, target: &str, text: &str);
+    /// Wait until what was sent is out.
+    fn flush(&self);
+    /// Leave the server.
+    fn quit(&self, text: &str);
+}
+
+impl Transport for IrcServer {
+    fn privmsg(&self, target: &str, text: &str) {
+        self.send_privmsg(target, text).unwrap();
+    }
+
+    fn flush(&self) {
+        // Give the connection time to send everything out.
+        thread::sleep(Duration::new(1, 0));
+    }
+
+    fn quit(&self, text: &str) {
+        self.send_quit(text).unwrap();
+    }
+}
+
+/// A dry run: messages are only logged.
+pub struct DryRun;
+
+impl Transport for DryRun {
+    fn privmsg(&self, target: &str, text: &str) {
+        let msg = format!("dry run, not sent: {}", text);
+        match target.starts_with("#") {
+            true => {
+                let ctx = Context { channel: Some(target.to_string()), ..Context::default() };
+                logging::info(&ctx, &msg)
+            }
+            false => logging::trace(&Context::nick(target), &msg),
+        }
+    }
+
+    fn flush(&self) {}
+
+    fn quit(&self, text: &str) {
+        logging::info(&Context::default(), &format!("dry run, not quitting: {}", text));
+    }
 }
 
 /// Processes incoming messages.
 /// Takes a Message and a channel sender to issue events to.
 pub fn process_cmd(msg: Message, tx: &Sender<GameEvent>) {
-    match msg.prefix {
-        Some(ref s) => print!("Message from {}: ", s),
-        _ => (),
-    }
     match msg.command {
         Command::PRIVMSG(ref s1, ref s2) => {
+            let source = msg.prefix.as_ref().unwrap();
+            process_text(source, s1, s2, tx);
+        }
+        Command::NICK(ref new) => {
             let nick = msg.source_nickname().unwrap();
-            println!("to {} containing {}", s1, s2);
-            let cmd = s2.trim();
-            if cmd.starts_with('!') {
-                // We're getting a command.
-                let cmd_words = cmd.split_whitespace().collect::<Vec<_>>();
-                match cmd_words[0].to_lowercase().as_str() {
-                    "!join" => {
-                        if s1.starts_with("#") {
-                            tx.send(GameEvent::Join(nick.to_string())).unwrap();
-                        } else {
-                            tx.send(GameEvent::Notice(nick.to_string(),
-                                                        "This command must be issued in public."
-                                                            .to_string()))
-                                .unwrap();
-                        }
-                    }
-                    _ => println!("Unimplemented command: {}", cmd_words[0]),
-                }
-            }
+            tx.send(GameEvent::Rename(nick.to_string(), new.clone())).unwrap();
         }
-        Command::NICK(_) |
         Command::QUIT(_) |
         Command::PART(_, _) => {
             let nick = msg.source_nickname().unwrap();
             tx.send(GameEvent::Leave(nick.to_string())).unwrap();
         }
-        _ => print!("{}", msg.to_string()),
+        _ => logging::debug(&Context::default(), &format!("irc: {}", msg.to_string().trim())),
     }
     let mstr = msg.to_string();
     if mstr.contains("exitnow") {
-        println!("Received Quit command, sending IRC quit event.");
-        tx.send(GameEvent::Notice("".to_string(),
-                                    "Owner asked me to quit, do vstrechi!".to_string()))
-            .unwrap();
+        logging::info(&Context::default(), "received quit command, sending IRC quit event");
         tx.send(GameEvent::Quit).unwrap()
     }
 }
 
+/// Processes a message sent by a nick to a channel, or to the bot privately.
+/// The sender is given as nick!user@host, or by nick alone offline.
+pub fn process_text(source: &str, s1: &str, s2: &str, tx: &Sender<GameEvent>) {
+    let nick = source.split('!').next().unwrap_or(source);
+    let ctx = Context {
+        channel: Some(s1.to_string()),
+        ..Context::nick(nick)
+    };
+    // Private messages to the bot carry night actions and team secrets.
+    match s1.starts_with("#") {
+        true => logging::debug(&ctx, &format!("message: {}", s2)),
+        false => logging::trace(&ctx, &format!("private message: {}", s2)),
+    }
+    let cmd = s2.trim();
+    if cmd.starts_with('!') {
+        // We're getting a command.
+        let cmd_words = cmd.split_whitespace().collect::<Vec<_>>();
+        match cmd_words[0].to_lowercase().as_str() {
+            "!join" => {
+                if s1.starts_with("#") {
+                    tx.send(GameEvent::Join(nick.to_string())).unwrap();
+                } else {
+                    tx.send(GameEvent::PublicOnly(nick.to_string())).unwrap();
+                }
+            }
+            "!theme" => {
+                if !s1.starts_with("#") {
+                    tx.send(GameEvent::PublicOnly(nick.to_string())).unwrap();
+                } else if cmd_words.len() > 1 {
+                    tx.send(GameEvent::Theme(nick.to_string(),
+                                             cmd_words[1].to_lowercase(),
+                                             cmd_words.get(2)
+                                                 .map(|l| l.to_lowercase())))
+                        .unwrap();
+                }
+            }
+            "!setup" => tx.send(GameEvent::Setup(nick.to_string())).unwrap(),
+            "!stats" | "!history" => {
+                // Without a nick, players ask about themselves.
+                let who = cmd_words.get(1).unwrap_or(&nick).to_string();
+                let event = match cmd_words[0].to_lowercase().as_str() {
+                    "!stats" => GameEvent::Stats(nick.to_string(), who),
+                    _ => GameEvent::History(nick.to_string(), who),
+                };
+                tx.send(event).unwrap();
+            }
+            "!top" => tx.send(GameEvent::Top(nick.to_string())).unwrap(),
+            "!time" => tx.send(GameEvent::Time(nick.to_string())).unwrap(),
+            "!status" => tx.send(GameEvent::Status(nick.to_string())).unwrap(),
+            "!extend" | "!shorten" => {
+                // By half a minute, unless told otherwise.
+                let secs = cmd_words.get(1).and_then(|n| n.parse::<u32>().ok()).unwrap_or(30);
+                let secs = secs as i64;
+                let secs = match cmd_words[0].to_lowercase().as_str() {
+                    "!extend" => secs,
+                    _ => -secs,
+                };
+                tx.send(GameEvent::Extend(nick.to_string(), source.to_string(), secs)).unwrap();
+            }
+            "!denounce" => {
+                if !s1.starts_with("#") {
+                    tx.send(GameEvent::PublicOnly(nick.to_string())).unwrap();
+                } else if cmd_words.len() > 1 {
+                    tx.send(GameEvent::Denounce(nick.to_string(),
+                                                cmd_words[1].to_string()))
+                        .unwrap();
+                }
+            }
+            "!guilty" | "!innocent" => {
+                if s1.starts_with("#") {
+                    let ballot = match cmd_words[0].to_lowercase().as_str() {
+                        "!guilty" => Ballot::Guilty,
+                        _ => Ballot::Innocent,
+                    };
+                    tx.send(GameEvent::Vote(nick.to_string(), ballot)).unwrap();
+                } else {
+                    tx.send(GameEvent::PublicOnly(nick.to_string())).unwrap();
+                }
+            }
+            "!abstain" => {
+                if s1.starts_with("#") {
+                    tx.send(GameEvent::Abstain(nick.to_string())).unwrap();
+                } else {
+                    tx.send(GameEvent::PublicOnly(nick.to_string())).unwrap();
+                }
+            }
+            "!detain" | "!protect" | "!kill" | "!check" | "!recruit" | "!watch" => {
+                let kind = ActionKind::from_command(&cmd_words[0].to_lowercase());
+                if s1.starts_with("#") {
+                    tx.send(GameEvent::PrivateOnly(nick.to_string())).unwrap();
+                } else if let (Some(kind), Some(target)) = (kind, cmd_words.get(1)) {
+                    tx.send(GameEvent::Act(nick.to_string(), kind, target.to_string()))
+                        .unwrap();
+                }
+            }
+            "!team" => {
+                if s1.starts_with("#") {
+                    tx.send(GameEvent::PrivateOnly(nick.to_string())).unwrap();
+                } else {
+                    let text = cmd.split_once(char::is_whitespace).map_or("", |(_, t)| t);
+                    tx.send(GameEvent::TeamChat(nick.to_string(), text.to_string()))
+                        .unwrap();
+                }
+            }
+            "!will" => {
+                if s1.starts_with("#") {
+                    tx.send(GameEvent::PrivateOnly(nick.to_string())).unwrap();
+                } else {
+                    let text = cmd.split_once(char::is_whitespace).map_or("", |(_, t)| t);
+                    tx.send(GameEvent::Will(nick.to_string(), text.to_string()))
+                        .unwrap();
+                }
+            }
+            "!locale" => {
+                if cmd_words.len() > 1 {
+                    tx.send(GameEvent::Locale(nick.to_string(),
+                                              cmd_words[1].to_lowercase()))
+                        .unwrap();
+                }
+            }
+            _ => {
+                logging::debug(&ctx, &format!("unimplemented command: {}", cmd_words[0]))
+            }
+        }
+    } else if s1.starts_with("#") {
+        // Plain chatter in the channel; the game may have rules about it.
+        tx.send(GameEvent::Msg(nick.to_string(), cmd.to_string())).unwrap();
+    }
+}
+
+/// Whether a message shows the bot is now connected, or no longer is. The bot
+/// is connected once the server has sent its message of the day, or the bot
+/// has joined the game's channel.
+pub fn connection_change(msg: &Message, my_nick: &str, my_chan: &str) -> Option<bool> {
+    match msg.command {
+        Command::Response(Response::RPL_ENDOFMOTD, _, _) |
+        Command::Response(Response::ERR_NOMOTD, _, _) => Some(true),
+        Command::JOIN(ref chan, _, _) => {
+            match msg.source_nickname() {
+                Some(n) if n == my_nick && chan.eq_ignore_ascii_case(my_chan) => Some(true),
+                _ => None,
+            }
+        }
+        Command::ERROR(_) => Some(false),
+        _ => None,
+    }
+}
+
+/// What the bot's threads share of its connection.
+pub struct Link {
+    /// Whether the bot is connected.
+    pub connected: AtomicBool,
+    /// What the bot hears, in the order its events are issued.
+    pub transcript: Mutex<Transcript>,
+}
+
+impl Link {
+    /// A connection, not up yet, keeping a transcript.
+    pub fn new(transcript: Transcript) -> Link {
+        Link {
+            connected: AtomicBool::new(false),
+            transcript: Mutex::new(transcript),
+        }
+    }
+}
+
+/// Handle a message from the server: keep track of whether the bot is
+/// connected, then record the message and issue the events it carries.
+pub fn receive(msg: Message, my_nick: &str, my_chan: &str, link: &Link, tx: &Sender<GameEvent>) {
+    if let Some(up) = connection_change(&msg, my_nick, my_chan) {
+        if link.connected.swap(up, Ordering::SeqCst) != up {
+            let ctx = Context::default();
+            match up {
+                true => logging::info(&ctx, "connected, the clock is running"),
+                false => logging::warn(&ctx, "disconnected, the clock is paused"),
+            }
+        }
+    }
+    let mut transcript = link.transcript.lock().unwrap();
+    transcript.hear(&msg);
+    process_cmd(msg, tx)
+}
+
+/// A second has passed: tick the game, if the bot is connected. The game's
+/// clock only moves on ticks, so it stands still while the bot is away.
+pub fn send_tick(link: &Link, tx: &Sender<GameEvent>) {
+    if link.connected.load(Ordering::SeqCst) {
+        let mut transcript = link.transcript.lock().unwrap();
+        transcript.tick();
+        tx.send(GameEvent::Tick).unwrap();
+    }
+}
+
 /// Deliver takes a GameReaction and delivers all its mesages out.
 fn deliver(gr: &GameReaction, tx: &Sender<GameEvent>) {
     for i in gr.msg.iter() {
         match i.recipients {
             Recipients::Channel(ref s) => {
+                let ctx = Context { channel: Some(s.clone()), ..Context::default() };
+                logging::debug(&ctx, &format!("notice: {}", i.content));
                 tx.send(GameEvent::Notice(s.clone(), i.content.clone()))
                     .unwrap();
             }
             Recipients::Nicks(ref v) => {
                 for j in v.iter() {
+                    // Role assignments and other secrets only show at trace level.
+                    logging::trace(&Context::nick(j), &format!("notice: {}", i.content));
                     tx.send(GameEvent::Notice(j.clone(), i.content.clone()))
                         .unwrap();
                 }
@@ -85,68 +331,357 @@ fn deliver(gr: &GameReaction, tx: &Sender<GameEvent>) {
     }
 }
 
+/// Channel offline games are played in, unless one is configured.
+const OFFLINE_CHANNEL: &str = "#pravda";
+/// Nick private messages in offline games are sent to.
+const OFFLINE_NICK: &str = "pravdabot";
 
-fn main() {
+/// Read the configuration file, applying command line overrides. Offline
+/// games can do without one.
+fn load_config(args: &Args) -> Result<Config, String> {
+    let offline = matches!(args.mode, Mode::Console | Mode::Replay(_));
+    let mut config = match Config::load(&args.config) {
+        Ok(c) => c,
+        Err(_) if offline && !Path::new(&args.config).exists() => Config::default(),
+        Err(e) => return Err(format!("{}: {}", args.config, e)),
+    };
+    if let Some(ref ch) = args.channel {
+        config.channels = Some(vec![ch.clone()]);
+    }
+    if offline && config.channels.as_ref().is_none_or(|c| c.is_empty()) {
+        config.channels = Some(vec![OFFLINE_CHANNEL.to_string()]);
+    }
+    return Ok(config);
+}
 
-    println!("Welcome to CCCP. Building datastructures...");
-    let my_server = IrcServer::new("pravda.json").unwrap();
+/// Set up logging and build a game out of the configuration.
+fn load_game(config: &Config) -> Result<Game, String> {
+    let empty = HashMap::new();
+    let opts = config.options.as_ref().unwrap_or(&empty);
+    logging::init(Logger::from_options(opts).map_err(|e| format!("Bad logging options: {}", e))?);
+    let my_chan = config.channels
+        .as_ref()
+        .and_then(|c| c.first())
+        .ok_or("No channel configured.".to_string())?;
+    let mut my_game = Game::new(my_chan);
+    // A theme and locale can be set per channel as "theme:#channel" and
+    // "locale:#channel", or for all as "theme" and "locale".
+    let option = |key: &str| {
+        opts.get(&format!("{}:{}", key, my_chan))
+            .or(opts.get(key))
+            .cloned()
+    };
+    let name = option("theme").unwrap_or("soviet".to_string());
+    let locale = option("locale").unwrap_or(theme::DEFAULT_LOCALE.to_string());
+    match Theme::load(&name, &locale) {
+        Ok(t) => my_game.theme = t,
+        Err(e) => {
+            logging::warn(&my_game.context(None),
+                          &format!("cannot load theme, using default: {}", e))
+        }
+    }
+    // Setups are named, and read either from a setup file or the built-in ones.
+    // A broken setup file is a configuration error: refuse to run.
+    if let Some(name) = option("setup") {
+        let setup = match opts.get("setups") {
+            Some(path) => Setup::load(path, &name),
+            None => Setup::builtin(&name).ok_or(format!("No setup called {}.", name)),
+        };
+        my_game.setup = setup.map_err(|e| format!("Cannot load setup: {}", e))?;
+    }
+    my_game.settings = Settings::from_options(opts)
+        .map_err(|e| format!("Bad game settings: {}", e))?;
+    // Finished games are recorded in the "stats" file, by default pravda.stats.
+    let stats_path = opts.get("stats").cloned().unwrap_or("pravda.stats".to_string());
+    my_game.stats = Stats::load(&stats_path)
+        .map_err(|e| format!("Cannot load statistics: {}", e))?;
+    // Each finished game gets a report in the "reports" directory, by default reports.
+    let report_dir = opts.get("reports").cloned().unwrap_or("reports".to_string());
+    my_game.report_dir = Some(report_dir);
+    return Ok(my_game);
+}
+
+/// Host games on IRC. On a dry run, nothing is sent to the channel or players.
+/// SIGINT and SIGTERM quit as the owner's quit command does, saving a game in
+/// progress to the "snapshot" file, by default pravda.snapshot. What the bot
+/// hears is kept in the "transcript" file, by default pravda.transcript, for
+/// `pravdabot replay` to play the session's games again.
+fn run(config: Config, mut my_game: Game, dry_run: bool) {
+    logging::info(&Context::default(), "Welcome to CCCP. Building datastructures...");
+    let option = |key: &str, default: &str| {
+        config.options
+            .as_ref()
+            .and_then(|opts| opts.get(key).cloned())
+            .unwrap_or(default.to_string())
+    };
+    let snapshot_path = option("snapshot", "pravda.snapshot");
+    // Games are dealt from a seed the transcript starts with.
+    let seed = Rng::time_seed();
+    my_game.rng = Rng::new(seed);
+    let transcript = match Transcript::create(&option("transcript", "pravda.transcript"), seed) {
+        Ok(t) => t,
+        Err(e) => {
+            logging::error(&Context::default(), &format!("cannot keep a transcript: {}", e));
+            Transcript::none()
+        }
+    };
+    // The clock moves a second at each tick, and ticks only come while the
+    // bot is connected.
+    my_game.clock = Clock::manual();
+    let my_server = IrcServer::from_config(config).unwrap();
     let s = my_server.clone();
-    let my_chan = &my_server.config().clone().channels.unwrap()[0];
-    let mut my_game = Game::new(&my_chan.clone());
+    let my_chan = my_game.channel.clone();
     s.identify().unwrap();
 
     let (tx, rx) = channel();
 
     let tx2 = tx.clone();
     let tx3 = tx.clone();
+    signals::on_shutdown(tx.clone());
 
-    // This thread ticks every second.
-    let _ = thread::spawn(move || {
-                              // Give the game time to connect.
-                              thread::sleep(Duration::new(20, 0));
-                              loop {
-                                  thread::sleep(Duration::new(1, 0));
-                                  tx.send(GameEvent::Tick).unwrap();
-                              }
+    // This thread ticks every second, while the bot is connected.
+    let link = Arc::new(Link::new(transcript));
+    let ticking = link.clone();
+    let reading = link.clone();
+    let _ = thread::spawn(move || loop {
+                              thread::sleep(Duration::new(1, 0));
+                              send_tick(&ticking, &tx);
                           });
 
     let s2 = my_server.clone();
+    let my_nick = s.config().nickname().to_string();
 
-    let _ = thread::spawn(move || for msg in s2.iter() {
-                              match msg {
-                                  Ok(m_r) => process_cmd(m_r, &tx2),
-                                  _ => break,
-                              }
-                          });
+    let _ = thread::spawn(move || {
+        for msg in s2.iter() {
+            match msg {
+                Ok(m_r) => receive(m_r, &my_nick, &my_chan, &reading, &tx2),
+                _ => break,
+            }
+        }
+        reading.connected.store(false, Ordering::SeqCst);
+        logging::warn(&Context::default(), "connection lost, the clock is paused");
+    });
 
 
+    let out: Box<dyn Transport> = match dry_run {
+        true => Box::new(DryRun),
+        false => Box::new(s.clone()),
+    };
     loop {
-        let event = rx.recv().unwrap();
-        match event {
+        match rx.recv().unwrap() {
             GameEvent::Quit => {
-                println!("Quit event received! Quitting...");
-                thread::sleep(Duration::new(1, 0));
-                s.send_quit(&"Pravda goes bye-bye!".to_string()).unwrap();
+                shut_down(&my_game, &rx, &*out, Some(&snapshot_path));
+                link.transcript.lock().unwrap().flush();
                 break;
             }
+            event => my_game = handle_event(my_game, event, &*out, &tx3),
+        }
+    }
+}
+
+/// Handle an event, other than quitting: send notices out, and pass anything
+/// else to the game, queueing what it has to say as notices.
+pub fn handle_event(mut my_game: Game,
+                    event: GameEvent,
+                    out: &dyn Transport,
+                    tx: &Sender<GameEvent>)
+                    -> Game {
+    match event {
+        GameEvent::Notice(ref str1, ref str2) => {
+            let to = if !str1.is_empty() { str1 } else { &my_game.channel };
+            out.privmsg(to, str2);
+            my_game
+        }
+        _ => {
+            if let GameEvent::Tick = event {
+                my_game.clock.advance(Duration::new(1, 0));
+            }
+            // Here we must process the game event. Game::process does the
+            // relevant mutations, but we also require a function to send
+            // the messages to the IRC server here.
+            let my_game = my_game.process(event);
+            for i in my_game.pending.iter() {
+                deliver(i, tx);
+            }
+            my_game.clean_up()
+        }
+    }
+}
+
+/// Quit: take no more events, say what was already queued, tell the channel
+/// a game in progress is suspended, and save it before leaving the server.
+pub fn shut_down(my_game: &Game,
+                 rx: &Receiver<GameEvent>,
+                 out: &dyn Transport,
+                 snapshot_path: Option<&str>) {
+    logging::info(&my_game.context(None), "quit event received, quitting");
+    for event in rx.try_iter() {
+        match event {
             GameEvent::Notice(ref str1, ref str2) => {
-                if str1 != "" {
-                    send(str1, str2, s.clone());
-                } else {
-                    send(my_chan, str2, s.clone());
-                }
+                let to = if !str1.is_empty() { str1 } else { &my_game.channel };
+                out.privmsg(to, str2);
             }
-            _ => {
-                // Here we must process the game event. Game::process does the
-                // relevant mutations, but we also require a function to send
-                // the messages to the IRC server here.
-                my_game = my_game.process(event.clone());
-                for i in my_game.pending.iter() {
-                    deliver(i, &tx3);
+            _ => logging::debug(&my_game.context(None), "event dropped, quitting"),
+        }
+    }
+    let playing = my_game.phase != Phase::Inactive;
+    if playing {
+        out.privmsg(&my_game.channel, &my_game.theme.get("quit.suspended"));
+    }
+    out.privmsg(&my_game.channel, &my_game.theme.get("quit.notice"));
+    out.flush();
+    if let (true, Some(path)) = (playing, snapshot_path) {
+        match fs::write(path, my_game.snapshot()) {
+            Ok(_) => logging::info(&my_game.context(None), &format!("game saved to {}", path)),
+            Err(e) => {
+                logging::error(&my_game.context(None),
+                               &format!("cannot save the game: {}: {}", path, e))
+            }
+        }
+    }
+    out.quit(&my_game.theme.get("quit.message"));
+}
+
+/// Process an event in an offline game, printing what the bot says. Each tick
+/// is a second of game time.
+fn play_offline(mut g: Game, event: GameEvent) -> Game {
+    if let GameEvent::Tick = event {
+        g.clock.advance(Duration::new(1, 0));
+    }
+    let g = g.process(event);
+    for r in g.pending.iter() {
+        for m in r.msg.iter() {
+            match m.recipients {
+                Recipients::Channel(ref c) => println!("{}: {}", c, m.content),
+                Recipients::Nicks(ref v) => {
+                    for n in v.iter() {
+                        println!("-> {}: {}", n, m.content);
+                    }
                 }
-                my_game = my_game.clean_up();
             }
         }
     }
+    return g.clean_up();
+}
+
+/// Issue the events of a line of an offline game. Seeds are left to the caller.
+fn offline_line(line: &Line, channel: &str, tx: &Sender<GameEvent>) {
+    match *line {
+        Line::Say(ref source, ref text) => process_text(source, channel, text, tx),
+        Line::Tell(ref source, ref text) => process_text(source, OFFLINE_NICK, text, tx),
+        Line::Leave(ref nick) => tx.send(GameEvent::Leave(nick.clone())).unwrap(),
+        Line::Rename(ref nick, ref new) => {
+            tx.send(GameEvent::Rename(nick.clone(), new.clone())).unwrap()
+        }
+        Line::Tick(n) => {
+            for _ in 0..n {
+                tx.send(GameEvent::Tick).unwrap();
+            }
+        }
+        Line::Seed(_) | Line::Nothing => (),
+    }
+}
+
+/// Play a game on the terminal, a line of play at a time, until end of input.
+fn console(mut my_game: Game) {
+    // The clock only moves on ticks, so that "tick n" passes n seconds.
+    my_game.clock = Clock::manual();
+    let (tx, rx) = channel();
+    let tx2 = tx.clone();
+    let my_chan = my_game.channel.clone();
+    signals::on_shutdown(tx.clone());
+    // This thread ticks every second.
+    let _ = thread::spawn(move || loop {
+                              thread::sleep(Duration::new(1, 0));
+                              if tx.send(GameEvent::Tick).is_err() {
+                                  break;
+                              }
+                          });
+    let _ = thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line.map_err(|e| e.to_string()).and_then(|l| Line::parse(&l)) {
+                Ok(Line::Seed(_)) => eprintln!("Seeds are only for transcripts."),
+                Ok(l) => offline_line(&l, &my_chan, &tx2),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        tx2.send(GameEvent::Quit).unwrap();
+    });
+    loop {
+        match rx.recv().unwrap() {
+            GameEvent::Quit => break,
+            event => my_game = play_offline(my_game, event),
+        }
+    }
+}
+
+/// Replay a game transcript, as fast as it can be read. Returns the game as
+/// the transcript leaves it.
+pub fn replay(mut my_game: Game, path: &str) -> Result<Game, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let (tx, rx) = channel();
+    let my_chan = my_game.channel.clone();
+    // Each tick of the transcript is a second of game time.
+    my_game.clock = Clock::manual();
+    for (n, line) in contents.lines().enumerate() {
+        let line = Line::parse(line).map_err(|e| format!("{}: line {}: {}", path, n + 1, e))?;
+        if let Line::Seed(seed) = line {
+            my_game.rng = Rng::new(seed);
+        }
+        offline_line(&line, &my_chan, &tx);
+        while let Ok(event) = rx.try_recv() {
+            my_game = play_offline(my_game, event);
+        }
+    }
+    return Ok(my_game);
+}
+
+/// Report an error, and exit with a failure.
+fn fail(e: &str) -> ! {
+    eprintln!("pravdabot: {}", e);
+    process::exit(1);
+}
 
+fn main() {
+    let argv = env::args().skip(1).collect::<Vec<_>>();
+    let args = match Args::parse(&argv) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("pravdabot: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if args.mode == Mode::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    let config = load_config(&args).unwrap_or_else(|e| fail(&e));
+    let mut my_game = load_game(&config).unwrap_or_else(|e| fail(&e));
+    match args.mode {
+        Mode::Run => run(config, my_game, args.dry_run),
+        Mode::ValidateConfig => {
+            println!("{}: OK, playing in {} with the {} setup.",
+                     args.config,
+                     my_game.channel,
+                     my_game.setup.name);
+        }
+        Mode::Console | Mode::Replay(_) => {
+            // Offline games are not real games: keep them out of the statistics and reports.
+            my_game.stats.path = None;
+            my_game.report_dir = None;
+            match args.mode {
+                Mode::Replay(ref path) => {
+                    if let Err(e) = replay(my_game, path) {
+                        fail(&e);
+                    }
+                }
+                _ => console(my_game),
+            }
+        }
+        Mode::Help => (),
+    }
 }
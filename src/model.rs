@@ -2,11 +2,32 @@
 //! This module contains the game model: data structures and functions
 //! to handle them more or less independently of the communication and control parts.
 
-/// Maximum number of ticks on the counter:
-const MAX_TICKS: u8 = 8;
+use std::collections::HashMap;
+use std::time::Duration;
+use clock::Clock;
+use logging::{self, Context};
+use night::{self, ActionKind, NightAction};
+use report;
+use rng::Rng;
+use settings::{RelayPolicy, Settings, TieBreak};
+use setup::{Constraint, Reveal, Setup};
+use stats::{Fate, Record, Stats};
+use theme::Theme;
+
+/// Seconds left in a phase at which the channel is warned it is ending.
+const TIME_WARNINGS: [u64; 3] = [60, 30, 10];
+
+/// Maximum length of a last will, in characters.
+pub const MAX_WILL_LEN: usize = 300;
+
+/// Maximum length of a relayed team message, in characters.
+pub const MAX_CHAT_LEN: usize = 400;
+/// Deals tried when balancing roles by rating.
+const BALANCE_TRIES: usize = 50;
 
 
 /// Roles for the game.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Role {
     Worker,
     Saboteur,
@@ -19,21 +40,85 @@ pub enum Role {
     Stalin,
 }
 
+impl Role {
+    /// Name of the role's description in the theme.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Role::Worker => "role.worker",
+            Role::Saboteur => "role.saboteur",
+            Role::Commissar => "role.commissar",
+            Role::Chekist => "role.chekist",
+            Role::Militya => "role.militya",
+            Role::Cosmopolitan => "role.cosmopolitan",
+            Role::Spy => "role.spy",
+            Role::Mastermind => "role.mastermind",
+            Role::Stalin => "role.stalin",
+        }
+    }
+
+    /// Name of the role's short name in the theme.
+    pub fn name_key(&self) -> &'static str {
+        match *self {
+            Role::Worker => "role_name.worker",
+            Role::Saboteur => "role_name.saboteur",
+            Role::Commissar => "role_name.commissar",
+            Role::Chekist => "role_name.chekist",
+            Role::Militya => "role_name.militya",
+            Role::Cosmopolitan => "role_name.cosmopolitan",
+            Role::Spy => "role_name.spy",
+            Role::Mastermind => "role_name.mastermind",
+            Role::Stalin => "role_name.stalin",
+        }
+    }
+
+    /// Role for a name as written in setup files, ignoring case.
+    pub fn from_name(name: &str) -> Option<Role> {
+        match name.to_lowercase().as_str() {
+            "worker" => Some(Role::Worker),
+            "saboteur" => Some(Role::Saboteur),
+            "commissar" => Some(Role::Commissar),
+            "chekist" => Some(Role::Chekist),
+            "militya" => Some(Role::Militya),
+            "cosmopolitan" => Some(Role::Cosmopolitan),
+            "spy" => Some(Role::Spy),
+            "mastermind" => Some(Role::Mastermind),
+            "stalin" => Some(Role::Stalin),
+            _ => None,
+        }
+    }
+}
+
 /// Teams. Spies start neutral. They win if they survive to the end.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Team {
     Soviet,
     Foreign,
     Opposition,
 }
 
-/// Gun state.
-pub enum Gun {
-    Loaded,
-    Unloaded,
-    Broken,
+impl Team {
+    /// Name of the team's description in the theme.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Team::Soviet => "team.soviet",
+            Team::Foreign => "team.foreign",
+            Team::Opposition => "team.opposition",
+        }
+    }
+
+    /// Team for a name as written in setup files, ignoring case.
+    pub fn from_name(name: &str) -> Option<Team> {
+        match name.to_lowercase().as_str() {
+            "soviet" => Some(Team::Soviet),
+            "foreign" => Some(Team::Foreign),
+            "opposition" => Some(Team::Opposition),
+            _ => None,
+        }
+    }
 }
 
 /// Player structure.
+#[derive(Clone, Debug)]
 pub struct Player {
     pub nick: String,
     pub role: Role,
@@ -44,6 +129,10 @@ pub struct Player {
     pub night_voter: bool,
     pub real_team: Team,
     pub apparent_team: Team,
+    pub will: Option<String>,
+    /// The last day (or its night) the player did anything in; 0 if never.
+    pub last_active: u8,
+    pub death: Option<DeathCause>,
 }
 
 impl Player {
@@ -53,16 +142,13 @@ impl Player {
             nick: nick,
             alive: true,
             day_voter: true,
-            night_voter: match r {
-                Role::Worker => false,
-                _ => true,
-            },
+            night_voter: r != Role::Worker,
             turn_actions: 1,
             game_actions: match r {
-                Role::Cosmopolitan | Role::Mastermind | Role::Spy | Role::Worker |
-                Role::Saboteur | Role::Commissar | Role::Militya => 0,
+                Role::Cosmopolitan | Role::Spy | Role::Worker | Role::Saboteur |
+                Role::Commissar | Role::Militya => 0,
                 Role::Chekist => 8,
-                Role::Stalin => 1,
+                Role::Stalin | Role::Mastermind => 1,
             },
             real_team: match r {
                 Role::Saboteur | Role::Mastermind => Team::Opposition,
@@ -75,67 +161,185 @@ impl Player {
                 _ => Team::Soviet,
             },
             role: r,
+            will: None,
+            last_active: 0,
+            death: None,
         };
         return p;
     }
 
-    /// Outputs printout of the given player.
-    pub fn to_string(&self) -> String {
-        let role = match self.role {
-            Role::Worker => "glorious Soviet worker",
-            Role::Saboteur => "social fascist Trotskyite saboteur",
-            Role::Cosmopolitan => "rootless cosmopolitan Mensch",
-            Role::Stalin => "general secretary of the Party",
-            Role::Spy => "capitalist agent of influence",
-            Role::Mastermind => "dangerous Trotskyite theoretician",
-            Role::Chekist => "uncompromising CHEKA member",
-            Role::Militya => "self-sacrificing people's militsioner",
-            Role::Commissar => "cunning political commissar",
-        };
-        let team_r = match self.real_team {
-            Team::Soviet => "loyal citizen of the Union",
-            Team::Foreign => "foreign meddler",
-            Team::Opposition => "treasonous opposition member",
-        };
-        let team_a = match self.apparent_team {
-            Team::Soviet => "loyal citizen of the Union",
-            Team::Foreign => "foreign meddler",
-            Team::Opposition => "treasonous opposition member",
-        };
-        let mut result = format!("You, {}, are a {} and look like a {}, while being a {}. ",
-                                 self.nick,
-                                 role,
-                                 team_a,
-                                 team_r);
-        result = result +
-                 &format!("You have {} game actions and {} turn actions left. ",
-                          self.game_actions,
-                          self.turn_actions);
-        result = result +
-                 match self.alive {
-                     true => "You are still alive. ",
-                     false => "You are no longer alive. ",
-                 };
-        result = result +
-                 match self.day_voter {
-                     true => "You are a full voting member of the Soviet. ",
-                     false => "You have lost their voting rights at the Soviet. ",
-                 };
-        result = result +
-                 match self.night_voter {
-                     true => "You are busy at night.",
-                     false => "You sleep at night.",
-                 };
-        return result;
-    }
-}
-
-/// Phases of play.
+    /// Outputs printout of the given player, worded by the given theme.
+    pub fn to_string(&self, t: &Theme) -> String {
+        let role = t.get(self.role.key());
+        let team_r = t.get(self.real_team.key());
+        let team_a = t.get(self.apparent_team.key());
+        let game_actions = t.count("count.game_actions", self.game_actions as u32);
+        let turn_actions = t.count("count.turn_actions", self.turn_actions as u32);
+        let parts = [t.render("player.identity",
+                              &[("nick", &self.nick),
+                                ("role", &role),
+                                ("apparent", &team_a),
+                                ("real", &team_r)]),
+                     t.render("player.actions",
+                              &[("game_actions", &game_actions),
+                                ("turn_actions", &turn_actions)]),
+                     t.get(match self.alive {
+                               true => "player.alive",
+                               false => "player.dead",
+                           }),
+                     t.get(match self.day_voter {
+                               true => "player.voter",
+                               false => "player.nonvoter",
+                           }),
+                     t.get(match self.night_voter {
+                               true => "player.night_busy",
+                               false => "player.night_sleeps",
+                           })];
+        return parts.join(" ");
+    }
+
+    /// What the channel is told about the player at their death, worded from
+    /// the same role and team descriptions as their summary.
+    pub fn epitaph(&self, t: &Theme, reveal: Reveal) -> String {
+        let team = |team: Team| {
+            t.render("reveal.team", &[("nick", &self.nick), ("team", &t.get(team.key()))])
+        };
+        match reveal {
+            Reveal::Role => {
+                t.render("reveal.role",
+                         &[("nick", &self.nick), ("role", &t.get(self.role.key()))])
+            }
+            Reveal::Team => team(self.real_team),
+            Reveal::ApparentTeam => team(self.apparent_team),
+            Reveal::Nothing => t.render("reveal.none", &[("nick", &self.nick)]),
+        }
+    }
+
+    /// Whether the player won a game that a team won. Foreign agents play for
+    /// no side at home: they win by living to see the end.
+    pub fn won(&self, winner: Team) -> bool {
+        match self.real_team {
+            Team::Foreign => self.alive,
+            team => team == winner,
+        }
+    }
+}
+
+/// Ways a player can die.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeathCause {
+    Tribunal,
+    NightKill,
+    /// The Militya died in place of the player they guarded.
+    Sacrifice,
+    /// The player did nothing for too long.
+    Inactivity,
+    /// The player left the channel.
+    Departure,
+}
+
+impl DeathCause {
+    /// Name of the death announcement in the theme.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            DeathCause::Tribunal => "death.tribunal",
+            DeathCause::NightKill => "death.night_kill",
+            DeathCause::Sacrifice => "death.sacrifice",
+            DeathCause::Inactivity => "death.inactivity",
+            DeathCause::Departure => "death.departure",
+        }
+    }
+}
+
+/// Make text safe to repeat in the channel: drop IRC formatting and control
+/// codes, and cut it to at most max characters.
+pub fn sanitize(text: &str, max: usize) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x03' {
+            // Colour codes carry up to two digits of foreground, then
+            // optionally a comma and two more of background.
+            for _ in 0..2 {
+                if chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+                    chars.next();
+                }
+            }
+            let mut lookahead = chars.clone();
+            if lookahead.next() == Some(',') &&
+               lookahead.peek().is_some_and(|d| d.is_ascii_digit()) {
+                chars.next();
+                for _ in 0..2 {
+                    if chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+                        chars.next();
+                    }
+                }
+            }
+        } else if c.is_control() {
+            // Line breaks would end the IRC line early; keep them as spaces.
+            if c == '\n' || c == '\r' || c == '\t' {
+                result.push(' ');
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    return result.trim().chars().take(max).collect();
+}
+
+/// A night visit: a player using their ability on another. Visits are kept
+/// for the whole game so that roles can find out who went where.
+#[derive(Clone, Debug)]
+pub struct Visit {
+    pub night: u8,
+    pub visitor: String,
+    pub role: Role,
+    pub kind: ActionKind,
+    pub target: String,
+}
+
+/// A vote on the accused.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ballot {
+    Guilty,
+    Innocent,
+    Abstain,
+}
+
+/// The outcome of the vote on the accused, as it is kept in the log.
+#[derive(Clone, Debug)]
+pub struct Tally {
+    pub day: u8,
+    pub accused: String,
+    pub guilty: Vec<String>,
+    pub innocent: Vec<String>,
+    pub abstained: Vec<String>,
+    pub executed: bool,
+}
+
+/// Phases of play. Days are numbered, and split into the denunciation of
+/// suspects, the trial of the accused, and the verdict on them.
+#[derive(Clone, PartialEq, Debug)]
 pub enum Phase {
-    Day(u8),
+    Denunciation(u8),
+    Trial(u8, String),
+    Verdict(u8, String),
     Night(u8),
     Inactive,
-    Starting(u8),
+    Starting,
+}
+
+impl Phase {
+    /// Number of the day being played, counting its night, if a game is on.
+    pub fn day(&self) -> Option<u8> {
+        match *self {
+            Phase::Denunciation(d) |
+            Phase::Trial(d, _) |
+            Phase::Verdict(d, _) |
+            Phase::Night(d) => Some(d),
+            Phase::Inactive | Phase::Starting => None,
+        }
+    }
 }
 
 // The game.
@@ -161,7 +365,7 @@ pub struct GameMessage {
 
 impl GameMessage {
     /// Create a public GameMessage.
-    fn public(ch: String, content: String) -> GameMessage {
+    pub fn public(ch: String, content: String) -> GameMessage {
         let r = Recipients::Channel(ch);
         let gm = GameMessage {
             recipients: r,
@@ -169,40 +373,93 @@ impl GameMessage {
         };
         return gm;
     }
+
+    /// Create a private GameMessage for a single nick.
+    pub fn private(nick: String, content: String) -> GameMessage {
+        let r = Recipients::Nicks(vec![nick]);
+        let gm = GameMessage {
+            recipients: r,
+            content: content,
+        };
+        return gm;
+    }
 }
 
 /// A game event.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum GameEvent {
     Join(String),
     Leave(String),
-    Msg(String),
+    /// A nick changes to another, and keeps its place in the game.
+    Rename(String, String),
+    Msg(String, String),
     Night(u8, u8),
     Day(u8, u8),
     Quit,
     Tick,
     Notice(String, String),
     Begin,
+    PublicOnly(String),
+    Theme(String, String, Option<String>),
+    Locale(String, String),
+    Setup(String),
+    Will(String, String),
+    PrivateOnly(String),
+    TeamChat(String, String),
+    Denounce(String, String),
+    Vote(String, Ballot),
+    Abstain(String),
+    Tribunal(Tally),
+    Act(String, ActionKind, String),
+    GameOver(Team),
+    Stats(String, String),
+    Top(String),
+    History(String, String),
+    Time(String),
+    /// A player or spectator asks what they know of the game.
+    Status(String),
+    /// An admin, by nick and nick!user@host, moves the end of the current
+    /// phase by some seconds.
+    Extend(String, String, i64),
+}
+
+impl GameEvent {
+    /// The player who did something in the game, for events that count as activity.
+    pub fn actor(&self) -> Option<&String> {
+        match *self {
+            GameEvent::Msg(ref n, _) |
+            GameEvent::Will(ref n, _) |
+            GameEvent::TeamChat(ref n, _) |
+            GameEvent::Denounce(ref n, _) |
+            GameEvent::Vote(ref n, _) |
+            GameEvent::Abstain(ref n) |
+            GameEvent::Act(ref n, _, _) => Some(n),
+            _ => None,
+        }
+    }
 }
 
 /// A game event and the messages it generates.
 pub struct GameReaction {
     pub event: GameEvent,
+    /// The phase the event came in, once Game::process is done with it.
+    pub phase: Option<Phase>,
     pub msg: Vec<GameMessage>,
 }
 
 impl GameReaction {
     /// Create a GameReaction out of an event.
-    fn new(e: &GameEvent) -> GameReaction {
+    pub fn new(e: &GameEvent) -> GameReaction {
         let gr = GameReaction {
             event: e.clone(),
+            phase: None,
             msg: Vec::new(),
         };
         return gr;
     }
 
     /// Add a message to an existing reaction.
-    fn add(&mut self, msg: GameMessage) -> &GameReaction {
+    pub fn add(&mut self, msg: GameMessage) -> &GameReaction {
         self.msg.push(msg);
         return self;
     }
@@ -211,37 +468,628 @@ impl GameReaction {
 
 /// The game state.
 pub struct Game {
-    pub gun: Gun,
     pub players: Participants,
     pub phase: Phase,
     pub channel: String,
     pub log: Vec<GameReaction>,
     pub pending: Vec<GameReaction>,
-    pub ticks: u8,
+    pub clock: Clock,
+    /// When the current phase ends, by the game's clock.
+    pub deadline: Duration,
+    /// The last warning given that the phase is ending, in seconds left.
+    pub warned: u64,
+    pub theme: Theme,
+    pub private_themes: HashMap<String, Theme>,
+    pub setup: Setup,
+    pub settings: Settings,
+    pub rng: Rng,
+    /// Who each player has denounced today. None is a vote to try nobody.
+    pub nominations: HashMap<String, Option<String>>,
+    /// Verdict votes on the accused, in the order they were cast.
+    pub votes: Vec<(String, Ballot)>,
+    /// Every night visit so far.
+    pub visits: Vec<Visit>,
+    /// Night actions submitted tonight, to be resolved at dawn.
+    pub actions: Vec<NightAction>,
+    /// Records of finished games.
+    pub stats: Stats,
+    /// Directory post-game reports are written to; None to keep none.
+    pub report_dir: Option<String>,
 }
 
 impl Game {
     /// Create game bound to a channel string.
-    pub fn new(ch: &String) -> Game {
+    pub fn new(ch: &str) -> Game {
         let s = Game {
-            gun: Gun::Loaded,
-            channel: ch.clone(),
+            channel: ch.to_string(),
             players: Participants::Joiners(Vec::new()),
             phase: Phase::Inactive,
             log: Vec::new(),
             pending: Vec::new(),
-            ticks: MAX_TICKS,
+            clock: Clock::system(),
+            deadline: Duration::from_secs(0),
+            warned: 0,
+            theme: Theme::default(),
+            private_themes: HashMap::new(),
+            setup: Setup::default(),
+            settings: Settings::default(),
+            rng: Rng::from_time(),
+            nominations: HashMap::new(),
+            votes: Vec::new(),
+            visits: Vec::new(),
+            actions: Vec::new(),
+            stats: Stats::default(),
+            report_dir: None,
         };
         return s;
     }
 
-    pub fn start(&mut self) {
-        println!("To be done. Game starting..."); // TODO
+    /// Context of log records about the game, and a nick if any.
+    pub fn context(&self, nick: Option<&str>) -> Context {
+        let playing = self.phase.day().is_some();
+        let c = Context {
+            channel: Some(self.channel.clone()),
+            game: match playing {
+                true => Some(self.stats.next_game()),
+                false => None,
+            },
+            phase: Some(format!("{:?}", self.phase)),
+            nick: nick.map(|n| n.to_string()),
+        };
+        return c;
+    }
+
+    /// Theme to word private messages to a nick with: the locale they picked,
+    /// or the channel's.
+    pub fn text_for(&self, nick: &str) -> &Theme {
+        match self.private_themes.get(nick) {
+            Some(t) => t,
+            None => &self.theme,
+        }
+    }
+
+    /// Start the game: deal the setup's roles to the joiners and tell each their own.
+    /// Returns false, leaving the game untouched, if the setup has no lineup
+    /// for this many players.
+    pub fn start(&mut self) -> bool {
+        let nicks = match self.players {
+            Participants::Joiners(ref v) => v.clone(),
+            Participants::Players(_) => return false,
+        };
+        let mut roles = match self.setup.lineup(nicks.len()) {
+            Some(l) => l.clone(),
+            None => return false,
+        };
+        self.rng.shuffle(&mut roles);
+        if self.settings.balance {
+            roles = self.balanced_deal(&nicks, roles);
+        }
+        let mut gr = GameReaction::new(&GameEvent::Begin);
+        let mut players = Vec::new();
+        for (nick, role) in nicks.into_iter().zip(roles) {
+            let p = self.setup.new_player(nick, role);
+            logging::trace(&self.context(Some(&p.nick)), &format!("dealt role {:?}", p.role));
+            gr.add(GameMessage::private(p.nick.clone(), p.to_string(self.text_for(&p.nick))));
+            players.push(p);
+        }
+        let count = players.len().to_string();
+        let text = self.theme.render("start.begin",
+                                     &[("count", &count),
+                                       ("roles", &self.lineup_names(players.len()))]);
+        gr.msg.insert(0, GameMessage::public(self.channel.clone(), text));
+        self.players = Participants::Players(players);
+        self.visits.clear();
+        logging::info(&self.context(None),
+                      &format!("game started with {} players, setup {}", count, self.setup.name));
+        self.enter_phase(Phase::Denunciation(1), &mut gr);
+        self.pending.push(gr);
+        return true;
+    }
+
+    /// The fairest of a number of random deals: the one where the Opposition's
+    /// average rating is closest to everybody else's.
+    fn balanced_deal(&mut self, nicks: &[String], mut roles: Vec<Role>) -> Vec<Role> {
+        let (stats, setup) = (&self.stats, &self.setup);
+        let imbalance = |roles: &[Role]| {
+            let (mut opposition, mut others) = (Vec::new(), Vec::new());
+            for (nick, &role) in nicks.iter().zip(roles.iter()) {
+                let rating = stats.rating(nick);
+                match setup.new_player(String::new(), role).real_team {
+                    Team::Opposition => opposition.push(rating),
+                    _ => others.push(rating),
+                }
+            }
+            let average = |v: &Vec<f64>| v.iter().sum::<f64>() / (v.len().max(1) as f64);
+            (average(&opposition) - average(&others)).abs()
+        };
+        let mut best = roles.clone();
+        let mut best_score = imbalance(&best);
+        for _ in 0..BALANCE_TRIES {
+            self.rng.shuffle(&mut roles);
+            let score = imbalance(&roles);
+            if score < best_score {
+                best = roles.clone();
+                best_score = score;
+            }
+        }
+        return best;
+    }
+
+    /// Seconds left before the current phase ends, rounded up.
+    pub fn remaining(&self) -> u64 {
+        let now = self.clock.now();
+        if self.deadline <= now {
+            return 0;
+        }
+        let left = self.deadline - now;
+        return left.as_secs() + (left.subsec_nanos() > 0) as u64;
+    }
+
+    /// Make the current phase end in some seconds from now. Warnings for
+    /// more time than that are not given.
+    pub fn set_remaining(&mut self, secs: u64) {
+        self.deadline = self.clock.now() + Duration::from_secs(secs);
+        self.warned = secs;
+    }
+
+    /// Warn the channel once the phase passes a warning time. If the bot missed
+    /// several, only the last one passed is given.
+    fn warn_time(&mut self) {
+        let left = self.remaining();
+        let warned = self.warned;
+        let due = TIME_WARNINGS.iter().cloned().filter(|&w| left <= w && w < warned).min();
+        if let Some(w) = due {
+            self.warned = w;
+            if left > 0 {
+                let mut gr = GameReaction::new(&GameEvent::Tick);
+                let time = self.theme.count("count.seconds", left as u32);
+                let text = self.theme.render("time.warning", &[("time", &time)]);
+                gr.add(GameMessage::public(self.channel.clone(), text));
+                self.pending.push(gr);
+            }
+        }
+    }
+
+    /// Move to a phase of play: reset its timer and announce it.
+    fn enter_phase(&mut self, phase: Phase, gr: &mut GameReaction) {
+        let (time, text) = match phase {
+            Phase::Denunciation(d) => {
+                self.nominations.clear();
+                (self.settings.denunciation_time,
+                 self.theme.render("phase.denunciation", &[("day", &d.to_string())]))
+            }
+            Phase::Trial(_, ref accused) => {
+                (self.settings.trial_time,
+                 self.theme.render("phase.trial", &[("accused", accused)]))
+            }
+            Phase::Verdict(_, ref accused) => {
+                self.votes.clear();
+                (self.settings.verdict_time,
+                 self.theme.render("phase.verdict", &[("accused", accused)]))
+            }
+            Phase::Night(d) => {
+                (self.settings.night_time,
+                 self.theme.render("phase.night", &[("day", &d.to_string())]))
+            }
+            Phase::Starting => (self.settings.start_time, String::new()),
+            Phase::Inactive => (0, String::new()),
+        };
+        self.phase = phase;
+        self.set_remaining(time as u64);
+        logging::debug(&self.context(None), "phase started");
+        if !text.is_empty() {
+            gr.add(GameMessage::public(self.channel.clone(), text));
+        }
+    }
+
+    /// Record a night visit.
+    pub fn visit(&mut self,
+                 night: u8,
+                 visitor: &str,
+                 role: Role,
+                 kind: ActionKind,
+                 target: &str) {
+        self.visits.push(Visit {
+            night: night,
+            visitor: visitor.to_string(),
+            role: role,
+            kind: kind,
+            target: target.to_string(),
+        });
+    }
+
+    /// Visits made to a target on a night.
+    pub fn visitors(&self, night: u8, target: &str) -> Vec<&Visit> {
+        self.visits.iter().filter(|v| v.night == night && v.target == target).collect()
+    }
+
+    /// Whether a nick belongs to a living player.
+    pub fn is_alive(&self, nick: &str) -> bool {
+        match self.players {
+            Participants::Players(ref v) => v.iter().any(|p| p.nick == nick && p.alive),
+            Participants::Joiners(_) => false,
+        }
+    }
+
+    /// Whether a nick belongs to a living player who may vote in the Soviet.
+    pub fn is_day_voter(&self, nick: &str) -> bool {
+        match self.players {
+            Participants::Players(ref v) => {
+                v.iter().any(|p| p.nick == nick && p.alive && p.day_voter)
+            }
+            Participants::Joiners(_) => false,
+        }
+    }
+
+    /// Smallest number of votes that is a strict majority of the living voters,
+    /// leaving out the given nick.
+    fn majority(&self, except: &str) -> usize {
+        let voters = match self.players {
+            Participants::Players(ref v) => {
+                v.iter().filter(|p| p.alive && p.day_voter && p.nick != except).count()
+            }
+            Participants::Joiners(_) => 0,
+        };
+        voters / 2 + 1
+    }
+
+    /// Nicks that cast a given ballot.
+    fn ballots(&self, b: Ballot) -> Vec<String> {
+        self.votes.iter().filter(|(_, x)| *x == b).map(|(n, _)| n.clone()).collect()
+    }
+
+    /// Decide a tied vote by the configured rule. Returns whether the accused
+    /// is executed, and the name of the rule's explanation in the theme.
+    fn break_tie(&mut self) -> (bool, &'static str) {
+        match self.settings.tie_break {
+            TieBreak::NoExecution => (false, "tie.none"),
+            TieBreak::Random => (self.rng.below(2) == 0, "tie.random"),
+            TieBreak::EarliestLeader => {
+                // Both sides end with the same count; whoever got there first wins.
+                let target = self.ballots(Ballot::Guilty).len();
+                let (mut guilty, mut innocent) = (0, 0);
+                for &(_, b) in self.votes.iter() {
+                    match b {
+                        Ballot::Guilty => guilty += 1,
+                        Ballot::Innocent => innocent += 1,
+                        Ballot::Abstain => (),
+                    }
+                    if guilty == target {
+                        return (true, "tie.earliest");
+                    }
+                    if innocent == target {
+                        return (false, "tie.earliest");
+                    }
+                }
+                (false, "tie.none")
+            }
+            TieBreak::Stalin => {
+                let stalin = match self.players {
+                    Participants::Players(ref v) => {
+                        v.iter()
+                            .find(|p| p.role == Role::Stalin && p.alive)
+                            .map(|p| p.nick.clone())
+                    }
+                    Participants::Joiners(_) => None,
+                };
+                let ballot = self.votes.iter().find(|(n, _)| Some(n) == stalin.as_ref());
+                match ballot {
+                    Some(&(_, Ballot::Guilty)) => (true, "tie.stalin"),
+                    Some(&(_, Ballot::Innocent)) => (false, "tie.stalin"),
+                    _ => (false, "tie.none"),
+                }
+            }
+        }
+    }
+
+    /// Count the verdict votes, announce the tally, carry out the sentence and
+    /// let night fall. The tally goes into the log as a Tribunal reaction.
+    fn resolve_verdict(&mut self, day: u8, accused: &str) {
+        let mut tally = Tally {
+            day: day,
+            accused: accused.to_string(),
+            guilty: self.ballots(Ballot::Guilty),
+            innocent: self.ballots(Ballot::Innocent),
+            abstained: self.ballots(Ballot::Abstain),
+            executed: false,
+        };
+        let mut texts = Vec::new();
+        let tied = tally.guilty.len() == tally.innocent.len() && !tally.guilty.is_empty();
+        tally.executed = if tied {
+            let (executed, key) = self.break_tie();
+            texts.push(self.theme.get("verdict.tie"));
+            texts.push(self.theme.get(key));
+            executed
+        } else {
+            tally.guilty.len() > tally.innocent.len()
+        };
+        let none = self.theme.get("verdict.nobody");
+        let list = |v: &Vec<String>| match v.is_empty() {
+            true => none.clone(),
+            false => v.join(", "),
+        };
+        texts.insert(0,
+                     self.theme.render("verdict.tally",
+                                       &[("guilty", &list(&tally.guilty)),
+                                         ("innocent", &list(&tally.innocent)),
+                                         ("abstained", &list(&tally.abstained))]));
+        let key = match tally.executed {
+            true => "verdict.guilty",
+            false => "verdict.innocent",
+        };
+        texts.push(self.theme.render(key,
+                                     &[("accused", accused),
+                                       ("guilty", &tally.guilty.len().to_string()),
+                                       ("innocent", &tally.innocent.len().to_string())]));
+        let executed = tally.executed;
+        let mut gr = GameReaction::new(&GameEvent::Tribunal(tally));
+        for t in texts.into_iter() {
+            gr.add(GameMessage::public(self.channel.clone(), t));
+        }
+        if executed {
+            self.kill(accused, DeathCause::Tribunal, &mut gr);
+        }
+        match self.winner() {
+            Some(team) => {
+                self.pending.push(gr);
+                self.end(team);
+            }
+            None => {
+                self.enter_phase(Phase::Night(day), &mut gr);
+                self.pending.push(gr);
+            }
+        }
+    }
+
+    /// The team that has won, if the game is over. The Soviets win once the
+    /// Opposition is gone; the Opposition wins once it is at least half of
+    /// the living.
+    pub fn winner(&self) -> Option<Team> {
+        let (opposition, others) = match self.players {
+            Participants::Players(ref v) => {
+                let alive = v.iter().filter(|p| p.alive);
+                alive.partition::<Vec<_>, _>(|p| p.real_team == Team::Opposition)
+            }
+            Participants::Joiners(_) => return None,
+        };
+        if opposition.is_empty() {
+            Some(Team::Soviet)
+        } else if opposition.len() >= others.len() {
+            Some(Team::Opposition)
+        } else {
+            None
+        }
+    }
+
+    /// End the game: announce the winners and open the archives on every
+    /// player's role, whatever the setup revealed at their death.
+    fn end(&mut self, team: Team) {
+        logging::info(&self.context(None), &format!("game over, won by {:?}", team));
+        let mut gr = GameReaction::new(&GameEvent::GameOver(team));
+        let text = self.theme.render("end.winner", &[("team", &self.theme.get(team.key()))]);
+        gr.add(GameMessage::public(self.channel.clone(), text));
+        let roles = match self.players {
+            Participants::Players(ref v) => {
+                v.iter()
+                    .map(|p| {
+                        self.theme.render("end.player",
+                                          &[("nick", &p.nick),
+                                            ("role", &self.theme.get(p.role.name_key()))])
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            Participants::Joiners(_) => String::new(),
+        };
+        let text = self.theme.render("end.roles", &[("roles", &roles)]);
+        gr.add(GameMessage::public(self.channel.clone(), text));
+        self.pending.push(gr);
+        // The report is written while the game is still in its last phase.
+        let game = self.stats.next_game();
+        self.save_report(game, team);
+        self.phase = Phase::Inactive;
+        self.actions.clear();
+        self.nominations.clear();
+        self.votes.clear();
+        self.record(team);
+    }
+
+    /// Write the report on the finished game, if reports are kept, and tell
+    /// the channel its file name. A report that cannot be written is logged.
+    fn save_report(&mut self, game: u32, winner: Team) {
+        let dir = match self.report_dir {
+            Some(ref d) => d.clone(),
+            None => return,
+        };
+        match report::save(self, &dir, game, winner) {
+            Ok(file) => {
+                let mut gr = GameReaction::new(&GameEvent::GameOver(winner));
+                let text = self.theme.render("end.report", &[("file", &file)]);
+                gr.add(GameMessage::public(self.channel.clone(), text));
+                self.pending.push(gr);
+            }
+            Err(e) => {
+                logging::error(&self.context(None), &format!("cannot save report: {}", e))
+            }
+        }
+    }
+
+    /// Add the finished game to the statistics. A record that cannot be saved
+    /// is reported, but does not stop the bot.
+    fn record(&mut self, winner: Team) {
+        let game = self.stats.next_game();
+        let records = match self.players {
+            Participants::Players(ref v) => {
+                v.iter()
+                    .map(|p| {
+                        Record {
+                            game: game,
+                            nick: p.nick.clone(),
+                            role: p.role,
+                            team: p.real_team,
+                            won: p.won(winner),
+                            fate: match p.death {
+                                None => Fate::Survived,
+                                Some(DeathCause::Tribunal) => Fate::Executed,
+                                Some(_) => Fate::Died,
+                            },
+                        }
+                    })
+                    .collect()
+            }
+            Participants::Joiners(_) => return,
+        };
+        if let Err(e) = self.stats.add_game(records) {
+            logging::error(&self.context(None), &format!("cannot save statistics: {}", e));
+        }
+    }
+
+    /// At the dawn after a day, remove living players who have done nothing
+    /// for too many days, unless idle_days is 0.
+    fn expire_idle(&mut self, day: u8, gr: &mut GameReaction) {
+        let limit = self.settings.idle_days;
+        if limit == 0 {
+            return;
+        }
+        let idle = match self.players {
+            Participants::Players(ref v) => {
+                v.iter()
+                    .filter(|p| p.alive && day.saturating_sub(p.last_active) >= limit)
+                    .map(|p| p.nick.clone())
+                    .collect::<Vec<_>>()
+            }
+            Participants::Joiners(_) => Vec::new(),
+        };
+        for nick in idle.iter() {
+            self.kill(nick, DeathCause::Inactivity, gr);
+        }
+    }
+
+    /// A player in the game, by nick.
+    pub fn player(&self, nick: &str) -> Option<&Player> {
+        match self.players {
+            Participants::Players(ref v) => v.iter().find(|p| p.nick == nick),
+            Participants::Joiners(_) => None,
+        }
+    }
+
+    /// A player in the game, by nick, to change them.
+    pub fn player_mut(&mut self, nick: &str) -> Option<&mut Player> {
+        match self.players {
+            Participants::Players(ref mut v) => v.iter_mut().find(|p| p.nick == nick),
+            Participants::Joiners(_) => None,
+        }
+    }
+
+    /// Index of a player in the game, if it has started and they are in it.
+    pub fn player_index(&self, nick: &str) -> Option<usize> {
+        match self.players {
+            Participants::Players(ref v) => v.iter().position(|p| p.nick == nick),
+            Participants::Joiners(_) => None,
+        }
+    }
+
+    /// Kill a player, adding the death announcement, what the setup reveals about
+    /// them and their last will, if any, to the reaction. Does nothing if they
+    /// are not alive in the game.
+    pub fn kill(&mut self, nick: &str, cause: DeathCause, gr: &mut GameReaction) {
+        let (will, epitaph) = match self.players {
+            Participants::Players(ref mut v) => {
+                match v.iter_mut().find(|p| p.nick == nick && p.alive) {
+                    Some(p) => {
+                        p.alive = false;
+                        p.death = Some(cause);
+                        (p.will.take(), p.epitaph(&self.theme, self.setup.reveal))
+                    }
+                    None => return,
+                }
+            }
+            Participants::Joiners(_) => return,
+        };
+        logging::info(&self.context(Some(nick)), &format!("died: {:?}", cause));
+        // The dead no longer denounce, vote or act.
+        self.nominations.remove(nick);
+        self.votes.retain(|(n, _)| n != nick);
+        self.actions.retain(|a| a.actor != nick);
+        let text = self.theme.render(cause.key(), &[("nick", nick)]);
+        gr.add(GameMessage::public(self.channel.clone(), text));
+        gr.add(GameMessage::public(self.channel.clone(), epitaph));
+        if let Some(w) = will {
+            let text = self.theme.render("will.published", &[("nick", nick), ("will", &w)]);
+            gr.add(GameMessage::public(self.channel.clone(), text));
+        }
+    }
+
+    /// Short role names of the lineup for a number of players, as one string.
+    fn lineup_names(&self, players: usize) -> String {
+        match self.setup.lineup(players) {
+            Some(l) => {
+                l.iter()
+                    .map(|r| self.theme.get(r.name_key()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+            None => String::new(),
+        }
+    }
+
+    /// The state of the game as text, one fact per line, to be kept when the
+    /// bot shuts down in the middle of a game. It holds every secret.
+    pub fn snapshot(&self) -> String {
+        let mut lines = vec!["# Pravdabot game snapshot.".to_string(),
+                             format!("channel {}", self.channel),
+                             format!("phase {:?}", self.phase),
+                             format!("remaining {}", self.remaining()),
+                             format!("setup {}", self.setup.name)];
+        match self.players {
+            Participants::Joiners(ref v) => {
+                for n in v.iter() {
+                    lines.push(format!("joiner {}", n));
+                }
+            }
+            Participants::Players(ref v) => {
+                for p in v.iter() {
+                    let state = match p.death {
+                        Some(cause) => format!("dead {:?}", cause),
+                        None => "alive".to_string(),
+                    };
+                    lines.push(format!("player {} {:?} {:?} {}",
+                                       p.nick,
+                                       p.role,
+                                       p.real_team,
+                                       state));
+                }
+            }
+        }
+        let mut nominations = self.nominations.iter().collect::<Vec<_>>();
+        nominations.sort();
+        for (nick, target) in nominations {
+            lines.push(format!("denounce {} {}", nick, target.as_ref().map_or("nobody", |t| t)));
+        }
+        for &(ref nick, ballot) in self.votes.iter() {
+            lines.push(format!("vote {} {:?}", nick, ballot));
+        }
+        for a in self.actions.iter() {
+            lines.push(format!("action {} {:?} {}", a.actor, a.kind, a.target));
+        }
+        for v in self.visits.iter() {
+            lines.push(format!("visit {} {} {:?} {}", v.night, v.visitor, v.kind, v.target));
+        }
+        return lines.join("\n") + "\n";
+    }
+
+    /// Reactions since the current or last game began, sent or not, oldest first.
+    pub fn game_log(&self) -> Vec<&GameReaction> {
+        let begun = self.log
+            .iter()
+            .rposition(|gr| matches!(gr.event, GameEvent::Begin))
+            .unwrap_or(0);
+        self.log[begun..].iter().chain(self.pending.iter()).collect()
     }
 
     /// Place pending reactions into log.
     pub fn clean_up(mut self: Game) -> Game {
-        if self.pending.len() > 0 {
+        if !self.pending.is_empty() {
             {
                 let m = &mut self.pending;
                 self.log.append(m);
@@ -252,40 +1100,109 @@ impl Game {
     }
 
     /// Process an event and return the new state of the game.
-    pub fn process(self, event: GameEvent) -> Game {
-        match event {
+    pub fn process(mut self, event: GameEvent) -> Game {
+        if let (Some(nick), Some(day)) = (event.actor(), self.phase.day()) {
+            if let Some(p) = self.player_mut(nick) {
+                p.last_active = day;
+            }
+        }
+        let phase = self.phase.clone();
+        let before = self.pending.len();
+        let mut g = match event {
             GameEvent::Tick => process_tick(self),
             GameEvent::Join(_) => process_join(self, event),
             GameEvent::Leave(_) => process_leave(self, event),
+            GameEvent::Rename(_, _) => process_rename(self, event),
+            GameEvent::PublicOnly(_) => process_public_only(self, event),
+            GameEvent::Theme(_, _, _) => process_theme(self, event),
+            GameEvent::Locale(_, _) => process_locale(self, event),
+            GameEvent::Setup(_) => process_setup(self, event),
+            GameEvent::Stats(_, _) => process_stats(self, event),
+            GameEvent::Top(_) => process_top(self, event),
+            GameEvent::History(_, _) => process_history(self, event),
+            GameEvent::Time(_) => process_time(self, event),
+            GameEvent::Status(_) => process_status(self, event),
+            GameEvent::Extend(_, _, _) => process_extend(self, event),
+            GameEvent::Will(_, _) => process_will(self, event),
+            GameEvent::PrivateOnly(_) => process_private_only(self, event),
+            GameEvent::TeamChat(_, _) => process_team_chat(self, event),
+            GameEvent::Denounce(_, _) => process_denounce(self, event),
+            GameEvent::Vote(_, _) => process_vote(self, event),
+            GameEvent::Abstain(_) => process_abstain(self, event),
+            GameEvent::Act(_, _, _) => night::process_act(self, event),
+            GameEvent::Msg(_, _) => process_msg(self, event),
             _ => {
-                println!("Unimplemented event!");
+                logging::warn(&self.context(None), "unimplemented event");
                 self
             }
+        };
+        for gr in g.pending.iter_mut().skip(before) {
+            gr.phase = Some(phase.clone());
         }
+        g
     }
 
     // Process phase change in the game.
     fn process_phase(self: &mut Game) -> &Game {
-        match self.phase {
+        match self.phase.clone() {
             // If we're on inactive phase, do nothing.
             Phase::Inactive => self,
-            // If Starting hase is over:
-            Phase::Starting(0) => {
-                println!("Cannot start, not enough players, also not written.");
-                self.phase = Phase::Inactive;
-                self.log = Vec::new();
-                self.players = Participants::Joiners(Vec::new());
-                self.ticks = MAX_TICKS;
+            // Phases move on when their time runs out.
+            _ if self.remaining() > 0 => self,
+            // If Starting phase is over:
+            Phase::Starting => {
+                if !self.start() {
+                    let mut gr = GameReaction::new(&GameEvent::Begin);
+                    let max = self.setup.max_players();
+                    let key = match self.players {
+                        Participants::Joiners(ref v) if v.len() > max => "start.too_many",
+                        _ => "start.cancelled",
+                    };
+                    let text = self.theme.render(key,
+                                                 &[("setup", &self.setup.name),
+                                                   ("max", &max.to_string())]);
+                    gr.add(GameMessage::public(self.channel.clone(), text));
+                    self.phase = Phase::Inactive;
+                    self.log = Vec::new();
+                    self.players = Participants::Joiners(Vec::new());
+                    self.pending.push(gr);
+                }
                 self
             }
-            Phase::Starting(i) => {
-                if self.ticks == 0 {
-                    self.phase = Phase::Starting(i - 1);
+            Phase::Denunciation(d) => {
+                let mut gr = GameReaction::new(&GameEvent::Tick);
+                let text = self.theme.get("phase.no_trial");
+                gr.add(GameMessage::public(self.channel.clone(), text));
+                self.enter_phase(Phase::Night(d), &mut gr);
+                self.pending.push(gr);
+                self
+            }
+            Phase::Trial(d, accused) => {
+                let mut gr = GameReaction::new(&GameEvent::Tick);
+                self.enter_phase(Phase::Verdict(d, accused), &mut gr);
+                self.pending.push(gr);
+                self
+            }
+            Phase::Verdict(d, accused) => {
+                self.resolve_verdict(d, &accused);
+                self
+            }
+            Phase::Night(d) => {
+                night::resolve(self, d);
+                let mut gr = GameReaction::new(&GameEvent::Tick);
+                self.expire_idle(d, &mut gr);
+                match self.winner() {
+                    Some(team) => {
+                        self.pending.push(gr);
+                        self.end(team);
+                    }
+                    None => {
+                        self.enter_phase(Phase::Denunciation(d + 1), &mut gr);
+                        self.pending.push(gr);
+                    }
                 }
                 self
             }
-            // If we don't match anything else:
-            _ => self,
         }
     }
 }
@@ -297,25 +1214,25 @@ fn process_join(mut g: Game, e: GameEvent) -> Game {
         let nick = nick.clone();
         match g.phase {
             Phase::Inactive => {
-                g.phase = Phase::Starting(6);
                 g.players = Participants::Joiners(vec![nick.clone()]);
                 let gm = GameMessage::public(g.channel.clone(),
-                                             format!("{} starting new game!", nick));
+                                             g.theme.render("join.start", &[("nick", &nick)]));
                 gr.add(gm);
+                g.enter_phase(Phase::Starting, &mut gr);
                 g.pending.push(gr);
                 g
             }
-            Phase::Starting(_) => {
+            Phase::Starting => {
                 if let Participants::Joiners(ref mut p) = g.players {
                     if !p.contains(&nick) {
-                        let gm = GameMessage::public(g.channel.clone(),
-                                                     format!("{} joins the game.", nick));
+                        let text = g.theme.render("join.joined", &[("nick", &nick)]);
+                        let gm = GameMessage::public(g.channel.clone(), text);
                         p.push(nick);
                         gr.add(gm);
                         g.pending.push(gr);
                     } else {
-                        let gm = GameMessage::public(g.channel.clone(),
-                                                     format!("{} already joined.", nick));
+                        let text = g.theme.render("join.already", &[("nick", &nick)]);
+                        let gm = GameMessage::public(g.channel.clone(), text);
                         gr.add(gm);
                         g.pending.push(gr);
                     }
@@ -324,8 +1241,7 @@ fn process_join(mut g: Game, e: GameEvent) -> Game {
             }
             _ => {
                 let gm = GameMessage::public(g.channel.clone(),
-                                             "A game is in place. Wait until it's over."
-                                                 .to_string());
+                                             g.theme.get("join.busy"));
                 gr.add(gm);
                 g.pending.push(gr);
                 g
@@ -342,23 +1258,22 @@ fn process_leave(mut g: Game, e: GameEvent) -> Game {
     if let GameEvent::Leave(ref nick) = e {
         let nick = nick.clone();
         match g.phase {
-            Phase::Starting(_) => {
+            Phase::Starting => {
                 if let Participants::Joiners(ref mut p) = g.players {
                     if !p.contains(&nick) {
-                        let gm = GameMessage::public(g.channel.clone(),
-                                                     format!("{} hasn't joined yet.", nick));
+                        let text = g.theme.render("leave.not_joined", &[("nick", &nick)]);
+                        let gm = GameMessage::public(g.channel.clone(), text);
                         gr.add(gm);
                     } else {
                         let index = p.iter().position(|it| it == &nick).unwrap();
                         p.remove(index);
-                        let gm = GameMessage::public(g.channel.clone(),
-                                                     format!("{} has left the game.", nick));
+                        let text = g.theme.render("leave.left", &[("nick", &nick)]);
+                        let gm = GameMessage::public(g.channel.clone(), text);
                         gr.add(gm);
-                        if p.len() == 0 {
+                        if p.is_empty() {
                             g.phase = Phase::Inactive;
                             let gm = GameMessage::public(g.channel.clone(),
-                                                         "No players left. Game cancelled."
-                                                             .to_string());
+                                                         g.theme.get("leave.cancelled"));
                             gr.add(gm);
                         }
                     }
@@ -366,8 +1281,28 @@ fn process_leave(mut g: Game, e: GameEvent) -> Game {
                 }
                 g
             }
+            Phase::Inactive => g,
             _ => {
-                println!("Leave event unimplementted for this phase.");
+                // Leaving in the middle of a game is a death like any other.
+                g.kill(&nick, DeathCause::Departure, &mut gr);
+                if !gr.msg.is_empty() {
+                    let winner = g.winner();
+                    // A trial cannot go on without its accused.
+                    let tried = match g.phase {
+                        Phase::Trial(d, ref a) | Phase::Verdict(d, ref a) if *a == nick => Some(d),
+                        _ => None,
+                    };
+                    if let (Some(d), None) = (tried, winner) {
+                        let text = g.theme.render("trial.departed", &[("nick", &nick)]);
+                        gr.add(GameMessage::public(g.channel.clone(), text));
+                        g.votes.clear();
+                        g.enter_phase(Phase::Denunciation(d), &mut gr);
+                    }
+                    g.pending.push(gr);
+                    if let Some(team) = winner {
+                        g.end(team);
+                    }
+                }
                 g
             }
         }
@@ -376,27 +1311,589 @@ fn process_leave(mut g: Game, e: GameEvent) -> Game {
     }
 }
 
-/// Process ticks.
+/// Process a nick change. Joiners and players keep their place under the new
+/// nick, along with their denunciations, votes and night actions; a nick that
+/// is already in the game, such as a dead player's, is not taken over, so
+/// changing to it is leaving instead.
+fn process_rename(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Rename(ref old, ref new) = e {
+        if let Some(t) = g.private_themes.remove(old) {
+            g.private_themes.insert(new.clone(), t);
+        }
+        let nicks = match g.players {
+            Participants::Joiners(ref v) => v.clone(),
+            Participants::Players(ref v) => v.iter().map(|p| p.nick.clone()).collect(),
+        };
+        if g.phase == Phase::Inactive || !nicks.contains(old) {
+            return g;
+        }
+        if nicks.contains(new) {
+            return process_leave(g, GameEvent::Leave(old.clone()));
+        }
+        let rename = |n: &mut String| if n == old {
+            *n = new.clone();
+        };
+        match g.players {
+            Participants::Joiners(ref mut v) => v.iter_mut().for_each(rename),
+            Participants::Players(ref mut v) => v.iter_mut().for_each(|p| rename(&mut p.nick)),
+        }
+        g.nominations = g.nominations
+            .drain()
+            .map(|(mut n, mut t)| {
+                rename(&mut n);
+                t.iter_mut().for_each(rename);
+                (n, t)
+            })
+            .collect();
+        g.votes.iter_mut().for_each(|v| rename(&mut v.0));
+        for v in g.visits.iter_mut() {
+            rename(&mut v.visitor);
+            rename(&mut v.target);
+        }
+        for a in g.actions.iter_mut() {
+            rename(&mut a.actor);
+            rename(&mut a.target);
+        }
+        match g.phase {
+            Phase::Trial(_, ref mut a) | Phase::Verdict(_, ref mut a) => rename(a),
+            _ => (),
+        }
+        let text = g.theme.render("rename.done", &[("nick", old), ("new", new)]);
+        gr.add(GameMessage::public(g.channel.clone(), text));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a player a command was used in the wrong place.
+fn process_public_only(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::PublicOnly(ref nick) = e {
+        let gm = GameMessage::private(nick.clone(), g.text_for(nick).get("cmd.public_only"));
+        gr.add(gm);
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a player a command must be sent to the bot privately.
+fn process_private_only(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::PrivateOnly(ref nick) = e {
+        let gm = GameMessage::private(nick.clone(), g.text_for(nick).get("cmd.private_only"));
+        gr.add(gm);
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Process last wills. Living players may write or rewrite theirs; an empty
+/// text shows the current one.
+fn process_will(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Will(ref nick, ref text) = e {
+        let text = sanitize(text, MAX_WILL_LEN);
+        let (key, will) = match g.players {
+            Participants::Players(ref mut v) => {
+                match v.iter_mut().find(|p| &p.nick == nick) {
+                    Some(ref p) if !p.alive => ("will.dead", None),
+                    Some(ref mut p) => {
+                        if text.is_empty() {
+                            match p.will {
+                                Some(ref w) => ("will.current", Some(w.clone())),
+                                None => ("will.empty", None),
+                            }
+                        } else {
+                            p.will = Some(text);
+                            ("will.saved", None)
+                        }
+                    }
+                    None => ("will.not_playing", None),
+                }
+            }
+            Participants::Joiners(_) => ("will.not_playing", None),
+        };
+        let reply = g.text_for(nick).render(key, &[("will", &will.unwrap_or_default())]);
+        gr.add(GameMessage::private(nick.clone(), reply));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Relay a message from a player to the other living members of their team.
+/// The Soviet team is large and its members do not know each other, so only
+/// the Opposition and foreign agents can talk in secret.
+fn process_team_chat(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::TeamChat(ref nick, ref text) = e {
+        let text = sanitize(text, MAX_CHAT_LEN);
+        let night = matches!(g.phase, Phase::Night(_));
+        let (error, team, spies) = match g.players {
+            Participants::Players(ref v) => {
+                match v.iter().find(|p| &p.nick == nick) {
+                    None => (Some("team.not_playing"), Vec::new(), Vec::new()),
+                    Some(p) if !p.alive => (Some("team.dead"), Vec::new(), Vec::new()),
+                    Some(p) => {
+                        let team = v.iter()
+                            .filter(|q| q.alive && q.real_team == p.real_team && &q.nick != nick)
+                            .map(|q| q.nick.clone())
+                            .collect::<Vec<_>>();
+                        let spies = v.iter()
+                            .filter(|q| q.alive && q.role == Role::Spy &&
+                                        q.real_team != p.real_team)
+                            .map(|q| q.nick.clone())
+                            .collect::<Vec<_>>();
+                        if p.real_team == Team::Soviet || team.is_empty() {
+                            (Some("team.alone"), team, spies)
+                        } else if g.settings.relay == RelayPolicy::NightOnly && !night {
+                            (Some("team.day"), team, spies)
+                        } else if text.is_empty() {
+                            (Some("team.empty"), team, spies)
+                        } else {
+                            (None, team, spies)
+                        }
+                    }
+                }
+            }
+            Participants::Joiners(_) => (Some("team.not_playing"), Vec::new(), Vec::new()),
+        };
+        match error {
+            Some(key) => {
+                gr.add(GameMessage::private(nick.clone(), g.text_for(nick).get(key)));
+            }
+            None => {
+                // Relayed messages are worded in the channel's locale, as one
+                // message goes to the whole team.
+                let content = g.theme.render("team.message", &[("nick", nick), ("text", &text)]);
+                gr.add(GameMessage {
+                    recipients: Recipients::Nicks(team),
+                    content: content,
+                });
+                if g.settings.spy_intercepts && !spies.is_empty() {
+                    let content = g.theme.render("team.intercepted", &[("text", &text)]);
+                    gr.add(GameMessage {
+                        recipients: Recipients::Nicks(spies),
+                        content: content,
+                    });
+                }
+            }
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Process denunciations. Once a suspect has enough of them, their trial begins.
+fn process_denounce(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Denounce(ref nick, ref accused) = e {
+        let day = match g.phase {
+            Phase::Denunciation(d) => d,
+            _ => {
+                let text = g.text_for(nick).get("denounce.not_now");
+                gr.add(GameMessage::private(nick.clone(), text));
+                g.pending.push(gr);
+                return g;
+            }
+        };
+        if !g.is_day_voter(nick) {
+            gr.add(GameMessage::private(nick.clone(), g.text_for(nick).get("denounce.no_vote")));
+        } else if !g.is_alive(accused) {
+            let text = g.text_for(nick).render("denounce.unknown", &[("accused", accused)]);
+            gr.add(GameMessage::private(nick.clone(), text));
+        } else {
+            g.nominations.insert(nick.clone(), Some(accused.clone()));
+            let count = g.nominations.values().filter(|a| a.as_ref() == Some(accused)).count();
+            let needed = g.settings.nominations as usize;
+            let text = g.theme.render("denounce.made",
+                                      &[("nick", nick),
+                                        ("accused", accused),
+                                        ("count", &count.to_string()),
+                                        ("needed", &needed.to_string())]);
+            gr.add(GameMessage::public(g.channel.clone(), text));
+            if count >= needed {
+                g.enter_phase(Phase::Trial(day, accused.clone()), &mut gr);
+            }
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Process verdict votes. The accused cannot vote on their own fate, and
+/// once either side has a strict majority the verdict is given at once.
+fn process_vote(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Vote(ref nick, ballot) = e {
+        let (key, day, accused) = match g.phase {
+            Phase::Verdict(_, ref accused) if accused == nick => {
+                (Some("vote.accused"), 0, String::new())
+            }
+            Phase::Verdict(d, ref accused) => (None, d, accused.clone()),
+            _ => (Some("vote.not_now"), 0, String::new()),
+        };
+        let key = match key {
+            None if !g.is_day_voter(nick) => Some("vote.no_vote"),
+            k => k,
+        };
+        if let Some(k) = key {
+            gr.add(GameMessage::private(nick.clone(), g.text_for(nick).get(k)));
+            g.pending.push(gr);
+            return g;
+        }
+        g.votes.retain(|(n, _)| n != nick);
+        g.votes.push((nick.clone(), ballot));
+        let vote = g.theme.get(match ballot {
+            Ballot::Guilty => "vote.guilty",
+            Ballot::Innocent => "vote.innocent",
+            Ballot::Abstain => "vote.abstain",
+        });
+        let text = g.theme.render("vote.cast", &[("nick", nick), ("vote", &vote)]);
+        gr.add(GameMessage::public(g.channel.clone(), text));
+        g.pending.push(gr);
+        let majority = g.majority(&accused);
+        if g.ballots(Ballot::Guilty).len() >= majority ||
+           g.ballots(Ballot::Innocent).len() >= majority {
+            g.resolve_verdict(day, &accused);
+        }
+    }
+    g
+}
+
+/// Process votes to execute nobody. While suspects are being denounced, a
+/// strict majority of them ends the day; during the verdict it is a blank vote.
+fn process_abstain(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Abstain(ref nick) = e {
+        let day = match g.phase {
+            Phase::Denunciation(d) => d,
+            Phase::Verdict(_, _) => {
+                return process_vote(g, GameEvent::Vote(nick.clone(), Ballot::Abstain))
+            }
+            _ => {
+                let text = g.text_for(nick).get("vote.not_now");
+                gr.add(GameMessage::private(nick.clone(), text));
+                g.pending.push(gr);
+                return g;
+            }
+        };
+        if !g.is_day_voter(nick) {
+            gr.add(GameMessage::private(nick.clone(), g.text_for(nick).get("denounce.no_vote")));
+        } else {
+            g.nominations.insert(nick.clone(), None);
+            let count = g.nominations.values().filter(|a| a.is_none()).count();
+            let needed = g.majority("");
+            let text = g.theme.render("abstain.made",
+                                      &[("nick", nick),
+                                        ("count", &count.to_string()),
+                                        ("needed", &needed.to_string())]);
+            gr.add(GameMessage::public(g.channel.clone(), text));
+            if count >= needed {
+                gr.add(GameMessage::public(g.channel.clone(), g.theme.get("phase.no_execution")));
+                g.enter_phase(Phase::Night(day), &mut gr);
+            }
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Process channel chatter. During a trial only the accused may speak, and
+/// living players who interrupt are called to order.
+fn process_msg(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Msg(ref nick, _) = e {
+        let interrupts = match g.phase {
+            Phase::Trial(_, ref accused) => accused != nick && g.is_alive(nick),
+            _ => false,
+        };
+        if interrupts {
+            gr.add(GameMessage::private(nick.clone(), g.text_for(nick).get("trial.silence")));
+            g.pending.push(gr);
+        }
+    }
+    g
+}
+
+/// Process theme changes. Only allowed while no game is in place.
+/// The channel keeps its locale unless a new one is given.
+fn process_theme(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Theme(ref nick, ref name, ref locale) = e {
+        let locale = locale.clone().unwrap_or(g.theme.locale.clone());
+        let text = match g.phase {
+            Phase::Inactive => {
+                match Theme::load(name, &locale) {
+                    Ok(t) => {
+                        g.theme = t;
+                        // Private messages keep their locale, but follow the theme.
+                        let mut reloaded = HashMap::new();
+                        for (n, t) in g.private_themes.iter() {
+                            if let Ok(t) = Theme::load(name, &t.locale) {
+                                reloaded.insert(n.clone(), t);
+                            }
+                        }
+                        g.private_themes = reloaded;
+                        g.theme.render("theme.changed", &[("nick", nick), ("theme", name)])
+                    }
+                    Err(err) => {
+                        logging::warn(&g.context(Some(nick)),
+                                      &format!("cannot load theme: {}", err));
+                        g.theme.render("theme.unknown", &[("theme", name)])
+                    }
+                }
+            }
+            _ => g.theme.get("theme.busy"),
+        };
+        gr.add(GameMessage::public(g.channel.clone(), text));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Process a player picking the locale of their private messages.
+fn process_locale(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Locale(ref nick, ref locale) = e {
+        let text = match Theme::load(&g.theme.name, locale) {
+            Ok(t) => {
+                let text = t.get("locale.changed");
+                g.private_themes.insert(nick.clone(), t);
+                text
+            }
+            Err(err) => {
+                logging::warn(&g.context(Some(nick)), &format!("cannot load locale: {}", err));
+                g.text_for(nick).render("locale.unknown", &[("locale", locale)])
+            }
+        };
+        gr.add(GameMessage::private(nick.clone(), text));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Describe the active setup to a player, privately.
+fn process_setup(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Setup(ref nick) = e {
+        let msgs = {
+            let t = g.text_for(nick);
+            let mut msgs = vec![t.render("setup.header", &[("setup", &g.setup.name)])];
+            for (count, lineup) in g.setup.lineups.iter() {
+                let roles = lineup.iter()
+                    .map(|r| t.get(r.name_key()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                msgs.push(t.render("setup.lineup",
+                                   &[("count", &count.to_string()), ("roles", &roles)]));
+            }
+            for c in g.setup.constraints.iter() {
+                let text = match *c {
+                    Constraint::Exactly(n, ref r) => {
+                        t.render("rule.exactly",
+                                 &[("n", &n.to_string()), ("role", &t.get(r.name_key()))])
+                    }
+                    Constraint::AtMost(n, ref r) => {
+                        t.render("rule.at_most",
+                                 &[("n", &n.to_string()), ("role", &t.get(r.name_key()))])
+                    }
+                    Constraint::AtLeast(n, ref r) => {
+                        t.render("rule.at_least",
+                                 &[("n", &n.to_string()), ("role", &t.get(r.name_key()))])
+                    }
+                    Constraint::From(ref r, n) => {
+                        t.render("rule.from",
+                                 &[("n", &n.to_string()), ("role", &t.get(r.name_key()))])
+                    }
+                };
+                msgs.push(text);
+            }
+            msgs
+        };
+        for m in msgs.into_iter() {
+            gr.add(GameMessage::private(nick.clone(), m));
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a player the statistics of a player, privately.
+fn process_stats(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Stats(ref nick, ref who) = e {
+        let msgs = {
+            let t = g.text_for(nick);
+            match g.stats.summary(who) {
+                Some(s) => {
+                    let rate = s.survived * 100 / s.games;
+                    let mut msgs = vec![t.render("stats.summary",
+                                                 &[("nick", who),
+                                                   ("rating",
+                                                    &format!("{:.0}", s.rating)),
+                                                   ("games", &t.count("count.games", s.games)),
+                                                   ("wins", &s.wins.to_string()),
+                                                   ("rate", &rate.to_string()),
+                                                   ("executed",
+                                                    &t.count("count.times", s.executed))])];
+                    for &(team, wins, losses) in s.teams.iter() {
+                        msgs.push(t.render("stats.team",
+                                           &[("team", &t.get(team.key())),
+                                             ("wins", &wins.to_string()),
+                                             ("losses", &losses.to_string())]));
+                    }
+                    let roles = s.roles
+                        .iter()
+                        .map(|&(r, n)| {
+                            t.render("stats.role",
+                                     &[("role", &t.get(r.name_key())), ("n", &n.to_string())])
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    msgs.push(t.render("stats.roles", &[("roles", &roles)]));
+                    msgs
+                }
+                None => vec![t.render("stats.unknown", &[("nick", who)])],
+            }
+        };
+        for m in msgs.into_iter() {
+            gr.add(GameMessage::private(nick.clone(), m));
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a player the leaderboard, privately.
+fn process_top(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Top(ref nick) = e {
+        let text = {
+            let t = g.text_for(nick);
+            let top = g.stats.top(5);
+            match top.is_empty() {
+                true => t.get("stats.top_empty"),
+                false => {
+                    let players = top.iter()
+                        .enumerate()
+                        .map(|(i, &(ref n, wins, games))| {
+                            t.render("stats.top_entry",
+                                     &[("rank", &(i + 1).to_string()),
+                                       ("nick", n),
+                                       ("wins", &wins.to_string()),
+                                       ("games", &games.to_string())])
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    t.render("stats.top", &[("players", &players)])
+                }
+            }
+        };
+        gr.add(GameMessage::private(nick.clone(), text));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a player the recent games of a player, privately.
+fn process_history(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::History(ref nick, ref who) = e {
+        let text = {
+            let t = g.text_for(nick);
+            let history = g.stats.history(who, 5);
+            match history.is_empty() {
+                true => t.render("stats.unknown", &[("nick", who)]),
+                false => {
+                    let games = history.iter()
+                        .map(|r| {
+                            let result = match r.won {
+                                true => "stats.won",
+                                false => "stats.lost",
+                            };
+                            t.render("stats.history_entry",
+                                     &[("game", &r.game.to_string()),
+                                       ("role", &t.get(r.role.name_key())),
+                                       ("result", &t.get(result)),
+                                       ("fate", &t.get(&format!("stats.{}", r.fate.key())))])
+                        })
+                        .collect::<Vec<_>>()
+                        .join("; ");
+                    t.render("stats.history", &[("nick", who), ("games", &games)])
+                }
+            }
+        };
+        gr.add(GameMessage::private(nick.clone(), text));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a player how long the current phase has left, privately.
+fn process_time(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Time(ref nick) = e {
+        let text = {
+            let t = g.text_for(nick);
+            match g.phase {
+                Phase::Inactive => t.get("time.none"),
+                _ => {
+                    let time = t.count("count.seconds", g.remaining() as u32);
+                    t.render("time.left", &[("time", &time)])
+                }
+            }
+        };
+        gr.add(GameMessage::private(nick.clone(), text));
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Tell a nick, privately, what they know of the game. Built on their view
+/// of it, so that it never holds another player's secrets.
+fn process_status(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Status(ref nick) = e {
+        let lines = g.view_for(nick).describe(g.text_for(nick));
+        for text in lines.into_iter() {
+            gr.add(GameMessage::private(nick.clone(), text));
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Let an admin lengthen or shorten the current phase. A phase shortened past
+/// its end is over on the next tick.
+fn process_extend(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Extend(ref nick, ref mask, secs) = e {
+        if !g.settings.is_admin(mask) {
+            let text = g.text_for(nick).get("time.not_admin");
+            gr.add(GameMessage::private(nick.clone(), text));
+        } else if g.phase == Phase::Inactive {
+            let text = g.text_for(nick).get("time.none");
+            gr.add(GameMessage::private(nick.clone(), text));
+        } else {
+            // However far it is pushed, a phase lasts between nothing and the
+            // longest a phase can be set to.
+            let left = (g.remaining() as i64).saturating_add(secs).clamp(0, u32::MAX as i64) as u64;
+            g.set_remaining(left);
+            logging::info(&g.context(Some(nick)), &format!("phase time set to {}s", left));
+            let time = g.theme.count("count.seconds", left as u32);
+            let text = g.theme.render("time.changed", &[("nick", nick), ("time", &time)]);
+            gr.add(GameMessage::public(g.channel.clone(), text));
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Process ticks: warn when the phase is ending, and end it when it is over.
 fn process_tick(mut g: Game) -> Game {
-    // println!("Ticks: {}", &g.ticks);
-    match g.phase {
-        // When game is inactive, do nothing.
-        Phase::Inactive => (),
-        // When game is starting...
-        Phase::Starting(s) => {
-            // println!("Phase: {}, ticks: {}", s, &g.ticks);
-            // If there are ticks left.
-            if g.ticks > 0 {
-                // Remove one.
-                g.ticks -= 1;
-                // No ticks left:
-            } else {
-                // Reset tick.
-                g.ticks = MAX_TICKS;
-            }
-        }
-        // Residual (FIX!)
-        _ => (),
+    if g.phase != Phase::Inactive {
+        g.warn_time();
     }
     g.process_phase();
     g
@@ -0,0 +1,387 @@
+//! Module: night.
+//! This module contains the night action engine. Actions are submitted during
+//! the night, and are all resolved together at dawn in a fixed priority order.
+//!
+//! Collisions are settled as follows:
+//! - Every player has one action of each kind a night; a new order replaces the old one.
+//! - The Opposition makes a single kill a night. When several members give
+//!   orders, the last one stands.
+//! - Everybody acts at the same time, so players killed in the night still
+//!   complete their own action. Actions on a target who was killed first
+//!   (recruitment) fail.
+//! - A Militya guarding the Opposition's target dies in their place.
+//!   When several guard them, the first to take up their post does, and the
+//!   others are told.
+//! - A player detained by the Commissar has no turn actions for the night, so
+//!   every action they ordered fails and they visit nobody.
+
+use std::collections::HashSet;
+use logging;
+use model::*;
+
+/// Kinds of night action, in the order they are resolved at dawn: detentions
+/// come first, and guards are posted before kills; then come investigations
+/// and recruitment. Watching comes last, to see every visit.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum ActionKind {
+    Block,
+    Protect,
+    Kill,
+    Investigate,
+    Recruit,
+    Watch,
+}
+
+impl ActionKind {
+    /// Kind of action for a command, as in "!kill".
+    pub fn from_command(cmd: &str) -> Option<ActionKind> {
+        match cmd {
+            "!detain" => Some(ActionKind::Block),
+            "!protect" => Some(ActionKind::Protect),
+            "!kill" => Some(ActionKind::Kill),
+            "!check" => Some(ActionKind::Investigate),
+            "!recruit" => Some(ActionKind::Recruit),
+            "!watch" => Some(ActionKind::Watch),
+            _ => None,
+        }
+    }
+
+    /// The command for the action, as in "!kill".
+    pub fn command(&self) -> &'static str {
+        match *self {
+            ActionKind::Block => "!detain",
+            ActionKind::Protect => "!protect",
+            ActionKind::Kill => "!kill",
+            ActionKind::Investigate => "!check",
+            ActionKind::Recruit => "!recruit",
+            ActionKind::Watch => "!watch",
+        }
+    }
+
+    /// Whether a player may perform this kind of action.
+    pub fn allowed(&self, p: &Player) -> bool {
+        match *self {
+            ActionKind::Block => p.role == Role::Commissar,
+            ActionKind::Protect => p.role == Role::Militya,
+            ActionKind::Kill => p.real_team == Team::Opposition && p.night_voter,
+            ActionKind::Investigate => p.role == Role::Chekist,
+            ActionKind::Recruit => p.role == Role::Mastermind,
+            ActionKind::Watch => p.role == Role::Spy,
+        }
+    }
+
+    /// Whether the action uses up one of the player's game actions.
+    pub fn limited(&self) -> bool {
+        match *self {
+            ActionKind::Investigate | ActionKind::Recruit => true,
+            ActionKind::Block | ActionKind::Protect | ActionKind::Kill |
+            ActionKind::Watch => false,
+        }
+    }
+
+    /// Name of the confirmation sent when the action is submitted.
+    fn queued_key(&self) -> &'static str {
+        match *self {
+            ActionKind::Block => "detain.queued",
+            ActionKind::Protect => "protect.queued",
+            ActionKind::Kill => "kill.queued",
+            ActionKind::Investigate => "check.queued",
+            ActionKind::Recruit => "recruit.queued",
+            ActionKind::Watch => "watch.set",
+        }
+    }
+}
+
+/// An action submitted for tonight.
+#[derive(Clone, Debug)]
+pub struct NightAction {
+    pub actor: String,
+    pub kind: ActionKind,
+    pub target: String,
+}
+
+/// Process a night action being submitted. It is checked now, and resolved at dawn.
+pub(crate) fn process_act(mut g: Game, e: GameEvent) -> Game {
+    let mut gr = GameReaction::new(&e);
+    if let GameEvent::Act(ref nick, kind, ref target) = e {
+        let error = match (g.phase.clone(), g.player(nick)) {
+            (Phase::Night(n), Some(p)) => {
+                if !p.alive || !kind.allowed(p) || p.turn_actions == 0 {
+                    Some("action.cannot")
+                } else if kind.limited() && p.game_actions == 0 {
+                    Some("action.used")
+                } else if !g.is_alive(target) || nick == target {
+                    Some("action.unknown")
+                } else if kind == ActionKind::Protect && guarded(&g, n - 1, nick, target) {
+                    Some("protect.again")
+                } else {
+                    None
+                }
+            }
+            (Phase::Night(_), None) => Some("action.cannot"),
+            _ => Some("action.not_now"),
+        };
+        if let Some(k) = error {
+            let text = g.text_for(nick).render(k, &[("target", target)]);
+            gr.add(GameMessage::private(nick.clone(), text));
+            g.pending.push(gr);
+            return g;
+        }
+        // The Opposition kills once a night: a new order replaces any other.
+        match kind {
+            ActionKind::Kill => g.actions.retain(|a| a.kind != ActionKind::Kill),
+            _ => g.actions.retain(|a| !(a.actor == *nick && a.kind == kind)),
+        }
+        logging::trace(&g.context(Some(nick)),
+                       &format!("night action {:?} on {}", kind, target));
+        g.actions.push(NightAction {
+            actor: nick.clone(),
+            kind: kind,
+            target: target.clone(),
+        });
+        let text = g.text_for(nick).render(kind.queued_key(), &[("target", target)]);
+        gr.add(GameMessage::private(nick.clone(), text));
+        if kind == ActionKind::Kill {
+            // The rest of the Opposition hears about the order.
+            let team = match g.players {
+                Participants::Players(ref v) => {
+                    v.iter()
+                        .filter(|p| p.alive && &p.nick != nick && ActionKind::Kill.allowed(p))
+                        .map(|p| p.nick.clone())
+                        .collect::<Vec<_>>()
+                }
+                Participants::Joiners(_) => Vec::new(),
+            };
+            for m in team.into_iter() {
+                let text = g.text_for(&m)
+                    .render("kill.ordered", &[("nick", nick), ("target", target)]);
+                gr.add(GameMessage::private(m, text));
+            }
+        }
+        g.pending.push(gr);
+    }
+    g
+}
+
+/// Resolve tonight's actions at dawn. Each outcome is logged as its own
+/// reaction, and every living player gets a private message about their night.
+pub(crate) fn resolve(g: &mut Game, night: u8) {
+    let mut actions = g.actions.drain(..).collect::<Vec<_>>();
+    // Sorting is stable, so orders of the same kind keep their turn.
+    actions.sort_by_key(|a| a.kind);
+    // Detained players lose their turn actions until dawn; what they had is
+    // given back once everything is resolved.
+    let mut detained = Vec::new();
+    for a in actions.iter().filter(|a| a.kind == ActionKind::Block) {
+        if !free(g, &a.actor) {
+            continue;
+        }
+        if let Some(p) = g.player_mut(&a.target) {
+            if p.turn_actions > 0 {
+                detained.push((p.nick.clone(), p.turn_actions));
+                p.turn_actions = 0;
+            }
+        }
+    }
+    let actions = actions.into_iter().filter(|a| free(g, &a.actor)).collect::<Vec<_>>();
+    // Everybody else goes out at the same time, so watchers see every visit.
+    for a in actions.iter() {
+        let role = g.player(&a.actor).map(|p| p.role).unwrap();
+        g.visit(night, &a.actor, role, a.kind, &a.target);
+    }
+    let mut told = HashSet::new();
+    let mut killed = Vec::new();
+    // Each Militya on guard, with the player they guard.
+    let mut guards: Vec<(String, String)> = Vec::new();
+    for a in actions.into_iter() {
+        let event = GameEvent::Act(a.actor.clone(), a.kind, a.target.clone());
+        let mut gr = GameReaction::new(&event);
+        match a.kind {
+            ActionKind::Block => {
+                let text = g.text_for(&a.actor).render("detain.done", &[("target", &a.target)]);
+                gr.add(GameMessage::private(a.actor.clone(), text));
+            }
+            ActionKind::Protect => {
+                // The Militya hears how the night went once the kill is known.
+                guards.push((a.actor.clone(), a.target.clone()));
+                continue;
+            }
+            ActionKind::Kill => {
+                // The first living guard steps in; any others see it happen.
+                let first = guards.iter().position(|(m, t)| {
+                    *t == a.target && g.is_alive(m) && g.is_alive(t)
+                });
+                match first {
+                    Some(i) => {
+                        let (m, _) = guards.remove(i);
+                        sacrifice(g, &a, &m, &mut gr);
+                        told.insert(m.clone());
+                        told.insert(a.target.clone());
+                        let (others, rest) = guards.into_iter().partition(|(_, t)| {
+                            *t == a.target
+                        });
+                        guards = rest;
+                        for (o, _) in others.into_iter().filter(|(o, _)| g.is_alive(o)) {
+                            let text = g.text_for(&o)
+                                .render("protect.spared", &[("nick", &m), ("target", &a.target)]);
+                            gr.add(GameMessage::private(o.clone(), text));
+                            told.insert(o);
+                        }
+                        killed.push(m);
+                    }
+                    None => {
+                        if g.is_alive(&a.target) {
+                            g.kill(&a.target, DeathCause::NightKill, &mut gr);
+                            killed.push(a.target.clone());
+                        }
+                        let text = g.text_for(&a.actor)
+                            .render("kill.done", &[("target", &a.target)]);
+                        gr.add(GameMessage::private(a.actor.clone(), text));
+                    }
+                }
+            }
+            ActionKind::Investigate => {
+                let team = g.player(&a.target).map(|p| p.apparent_team).unwrap();
+                if let Some(p) = g.player_mut(&a.actor) {
+                    p.game_actions = p.game_actions.saturating_sub(1);
+                }
+                let text = {
+                    let t = g.text_for(&a.actor);
+                    t.render("check.result",
+                             &[("target", &a.target), ("team", &t.get(team.key()))])
+                };
+                gr.add(GameMessage::private(a.actor.clone(), text));
+            }
+            ActionKind::Recruit => recruit(g, &a, &mut gr, &mut told),
+            ActionKind::Watch => {
+                let text = watch_report(g, night, &a);
+                gr.add(GameMessage::private(a.actor.clone(), text));
+            }
+        }
+        told.insert(a.actor.clone());
+        g.pending.push(gr);
+    }
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    for (nick, n) in detained.into_iter() {
+        if let Some(p) = g.player_mut(&nick) {
+            p.turn_actions = n;
+        }
+        if g.is_alive(&nick) {
+            let text = g.text_for(&nick).get("detain.held");
+            gr.add(GameMessage::private(nick.clone(), text));
+            told.insert(nick);
+        }
+    }
+    for (m, target) in guards.into_iter() {
+        if g.is_alive(&m) {
+            let text = g.text_for(&m).render("protect.quiet", &[("target", &target)]);
+            gr.add(GameMessage::private(m.clone(), text));
+            told.insert(m);
+        }
+    }
+    if killed.is_empty() {
+        gr.add(GameMessage::public(g.channel.clone(), g.theme.get("dawn.peaceful")));
+    }
+    let sleepers = match g.players {
+        Participants::Players(ref v) => {
+            v.iter()
+                .filter(|p| p.alive && !told.contains(&p.nick))
+                .map(|p| p.nick.clone())
+                .collect::<Vec<_>>()
+        }
+        Participants::Joiners(_) => Vec::new(),
+    };
+    for s in sleepers.into_iter() {
+        let text = g.text_for(&s).get("night.quiet");
+        gr.add(GameMessage::private(s, text));
+    }
+    g.pending.push(gr);
+}
+
+/// Whether a player still has a turn action tonight, and was not detained.
+fn free(g: &Game, nick: &str) -> bool {
+    g.player(nick).is_some_and(|p| p.turn_actions > 0)
+}
+
+/// Whether a Militya guarded a target on a night.
+fn guarded(g: &Game, night: u8, nick: &str, target: &str) -> bool {
+    g.visitors(night, target)
+        .iter()
+        .any(|v| v.visitor == nick && v.kind == ActionKind::Protect)
+}
+
+/// The Militya dies in place of the Opposition's target. The killer, the
+/// Militya and the target each learn what happened.
+fn sacrifice(g: &mut Game, a: &NightAction, militya: &str, gr: &mut GameReaction) {
+    let args = [("nick", militya), ("target", a.target.as_str())];
+    g.kill(militya, DeathCause::Sacrifice, gr);
+    let told = vec![(a.actor.as_str(), "kill.thwarted"),
+                    (militya, "protect.sacrificed"),
+                    (a.target.as_str(), "protect.saved")];
+    for (to, k) in told.into_iter() {
+        let text = g.text_for(to).render(k, &args);
+        gr.add(GameMessage::private(to.to_string(), text));
+    }
+}
+
+/// Resolve the Mastermind's recruitment. Workers are turned into Saboteurs;
+/// the Chekist and Stalin see through it and learn who tried; anybody else
+/// simply refuses.
+fn recruit(g: &mut Game, a: &NightAction, gr: &mut GameReaction, told: &mut HashSet<String>) {
+    let args = [("nick", a.actor.as_str()), ("target", a.target.as_str())];
+    if !g.is_alive(&a.target) {
+        let text = g.text_for(&a.actor).render("recruit.gone", &args);
+        gr.add(GameMessage::private(a.actor.clone(), text));
+        return;
+    }
+    if let Some(p) = g.player_mut(&a.actor) {
+        p.game_actions = p.game_actions.saturating_sub(1);
+    }
+    let target_role = g.player(&a.target).map(|p| p.role).unwrap();
+    if target_role == Role::Worker {
+        let p = g.player_mut(&a.target).unwrap();
+        p.role = Role::Saboteur;
+        p.real_team = Team::Opposition;
+        p.apparent_team = Team::Opposition;
+        p.night_voter = true;
+    }
+    let to_mastermind = match target_role {
+        Role::Worker => "recruit.success",
+        _ => "recruit.failed",
+    };
+    let text = g.text_for(&a.actor).render(to_mastermind, &args);
+    gr.add(GameMessage::private(a.actor.clone(), text));
+    let to_target = match target_role {
+        Role::Worker => Some("recruit.joined"),
+        Role::Chekist | Role::Stalin => Some("recruit.exposed"),
+        _ => None,
+    };
+    if let Some(k) = to_target {
+        let text = g.text_for(&a.target).render(k, &args);
+        gr.add(GameMessage::private(a.target.clone(), text));
+        told.insert(a.target.clone());
+    }
+}
+
+/// What a Spy learns about the visitors of the player they watched.
+fn watch_report(g: &Game, night: u8, a: &NightAction) -> String {
+    let t = g.text_for(&a.actor);
+    let seen = g.visitors(night, &a.target)
+        .into_iter()
+        .filter(|v| v.visitor != a.actor)
+        .map(|v| match g.settings.spy_sees_roles {
+            true => {
+                t.render("watch.visitor_role",
+                         &[("nick", &v.visitor), ("role", &t.get(v.role.name_key()))])
+            }
+            false => v.visitor.clone(),
+        })
+        .collect::<Vec<_>>();
+    match seen.is_empty() {
+        true => t.render("watch.quiet", &[("target", &a.target)]),
+        false => {
+            t.render("watch.report",
+                     &[("target", &a.target), ("visitors", &seen.join(", "))])
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Module: report.
+//! This module contains the report written when a game is over: one HTML
+//! page, with nothing to fetch, holding the roles, what was said in the
+//! channel day by day, the tallies of the votes, the night actions, and the
+//! private messages each player got. It is built from the game's log.
+
+use std::fs;
+use std::path::Path;
+use model::{DeathCause, Game, GameEvent, Participants, Recipients, Team};
+use stats::Fate;
+
+/// Make text safe to put in HTML.
+fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            _ => result.push(c),
+        }
+    }
+    return result;
+}
+
+/// A list of lines, as an HTML list.
+fn list(items: &[String]) -> String {
+    let mut html = "<ul>\n".to_string();
+    for i in items.iter() {
+        html.push_str(&format!("<li>{}</li>\n", escape(i)));
+    }
+    html.push_str("</ul>\n");
+    return html;
+}
+
+/// The report on a finished game, numbered as in the statistics, worded by
+/// the channel's theme.
+pub fn html(g: &Game, game: u32, winner: Team) -> String {
+    let t = &g.theme;
+    let log = g.game_log();
+    let title = t.render("report.title", &[("game", &game.to_string()), ("channel", &g.channel)]);
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                            <title>{0}</title>\n<style>\nbody {{ font-family: sans-serif; \
+                            max-width: 50em; margin: auto; }}\nli {{ margin: 0.2em 0; }}\n\
+                            </style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+                           escape(&title));
+    let won = t.render("end.winner", &[("team", &t.get(winner.key()))]);
+    html.push_str(&format!("<p>{}</p>\n", escape(&won)));
+    let players = match g.players {
+        Participants::Players(ref v) => v.iter().collect::<Vec<_>>(),
+        Participants::Joiners(_) => Vec::new(),
+    };
+    // The roles, as the game ended.
+    let roles = players.iter()
+        .map(|p| {
+            let fate = match p.death {
+                None => Fate::Survived,
+                Some(DeathCause::Tribunal) => Fate::Executed,
+                Some(_) => Fate::Died,
+            };
+            t.render("report.player",
+                     &[("nick", &p.nick),
+                       ("role", &t.get(p.role.name_key())),
+                       ("team", &t.get(p.real_team.key())),
+                       ("fate", &t.get(&format!("stats.{}", fate.key())))])
+        })
+        .collect::<Vec<_>>();
+    html.push_str(&format!("<h2>{}</h2>\n{}", escape(&t.get("report.roles")), list(&roles)));
+    // What the channel saw, a section for each day and its night.
+    html.push_str(&format!("<h2>{}</h2>\n", escape(&t.get("report.timeline"))));
+    let mut day = None;
+    let mut said = Vec::new();
+    for gr in log.iter() {
+        let public = gr.msg
+            .iter()
+            .filter(|m| match m.recipients {
+                Recipients::Channel(_) => true,
+                Recipients::Nicks(_) => false,
+            })
+            .map(|m| m.content.clone())
+            .collect::<Vec<_>>();
+        if public.is_empty() {
+            continue;
+        }
+        // The event that ended the game is still being processed.
+        let phase = gr.phase.as_ref().unwrap_or(&g.phase);
+        if said.is_empty() || phase.day() != day {
+            if !said.is_empty() {
+                html.push_str(&list(&said));
+                said.clear();
+            }
+            day = phase.day();
+            let heading = match day {
+                Some(d) => t.render("report.day", &[("day", &d.to_string())]),
+                None => t.get("report.start"),
+            };
+            html.push_str(&format!("<h3>{}</h3>\n", escape(&heading)));
+        }
+        said.extend(public);
+    }
+    if !said.is_empty() {
+        html.push_str(&list(&said));
+    }
+    // The tallies of every vote on an accused.
+    let none = t.get("verdict.nobody");
+    let names = |v: &Vec<String>| match v.is_empty() {
+        true => none.clone(),
+        false => v.join(", "),
+    };
+    let tallies = log.iter()
+        .filter_map(|gr| match gr.event {
+            GameEvent::Tribunal(ref tally) => Some(tally),
+            _ => None,
+        })
+        .map(|tally| {
+            let votes = t.render("verdict.tally",
+                                 &[("guilty", &names(&tally.guilty)),
+                                   ("innocent", &names(&tally.innocent)),
+                                   ("abstained", &names(&tally.abstained))]);
+            let outcome = t.get(match tally.executed {
+                true => "report.executed",
+                false => "report.spared",
+            });
+            t.render("report.tally",
+                     &[("day", &tally.day.to_string()),
+                       ("accused", &tally.accused),
+                       ("outcome", &outcome),
+                       ("votes", &votes)])
+        })
+        .collect::<Vec<_>>();
+    if !tallies.is_empty() {
+        let heading = t.get("report.tallies");
+        html.push_str(&format!("<h2>{}</h2>\n{}", escape(&heading), list(&tallies)));
+    }
+    // The night actions that were carried out, a section for each night.
+    if !g.visits.is_empty() {
+        html.push_str(&format!("<h2>{}</h2>\n", escape(&t.get("report.nights"))));
+        let mut nights = g.visits.iter().map(|v| v.night).collect::<Vec<_>>();
+        nights.dedup();
+        for n in nights.into_iter() {
+            let heading = t.render("report.night", &[("day", &n.to_string())]);
+            let visits = g.visits
+                .iter()
+                .filter(|v| v.night == n)
+                .map(|v| {
+                    t.render("report.visit",
+                             &[("nick", &v.visitor),
+                               ("role", &t.get(v.role.name_key())),
+                               ("action", v.kind.command()),
+                               ("target", &v.target)])
+                })
+                .collect::<Vec<_>>();
+            html.push_str(&format!("<h3>{}</h3>\n{}", escape(&heading), list(&visits)));
+        }
+    }
+    // Everything each player was told privately.
+    html.push_str(&format!("<h2>{}</h2>\n", escape(&t.get("report.messages"))));
+    for p in players.iter() {
+        let told = log.iter()
+            .flat_map(|gr| gr.msg.iter())
+            .filter(|m| match m.recipients {
+                Recipients::Nicks(ref v) => v.contains(&p.nick),
+                Recipients::Channel(_) => false,
+            })
+            .map(|m| m.content.clone())
+            .collect::<Vec<_>>();
+        html.push_str(&format!("<h3>{}</h3>\n{}", escape(&p.nick), list(&told)));
+    }
+    html.push_str("</body>\n</html>\n");
+    return html;
+}
+
+/// Write the report on a finished game into a directory, making it if need be.
+/// Returns the report's file name.
+pub fn save(g: &Game, dir: &str, game: u32, winner: Team) -> Result<String, String> {
+    let file = format!("game-{}.html", game);
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir, e))?;
+    let path = Path::new(dir).join(&file);
+    fs::write(&path, html(g, game, winner)).map_err(|e| format!("{}: {}", path.display(), e))?;
+    return Ok(file);
+}
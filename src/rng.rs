@@ -0,0 +1,59 @@
+//! Module: rng.
+//! A small pseudo-random number generator (xorshift64*), so that games can be
+//! dealt from a fixed seed in tests.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Random number generator state.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from a seed. Zero is not a valid state, so it is replaced.
+    pub fn new(seed: u64) -> Rng {
+        let r = Rng {
+            state: match seed {
+                0 => 0x9E37_79B9_7F4A_7C15,
+                _ => seed,
+            },
+        };
+        return r;
+    }
+
+    /// Create a generator seeded from the clock.
+    pub fn from_time() -> Rng {
+        Rng::new(Rng::time_seed())
+    }
+
+    /// A seed taken from the clock.
+    pub fn time_seed() -> u64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() ^ ((d.subsec_nanos() as u64) << 32),
+            Err(_) => 0,
+        }
+    }
+
+    /// Next number in the sequence.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    }
+
+    /// A number in 0..n. n must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Shuffle a slice in place (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, v: &mut [T]) {
+        let mut i = v.len();
+        while i > 1 {
+            let j = self.below(i);
+            i -= 1;
+            v.swap(i, j);
+        }
+    }
+}
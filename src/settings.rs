@@ -0,0 +1,196 @@
+//! Module: settings.
+//! This module contains the game settings operators can change from the bot's
+//! configuration options, and how they are read.
+
+use std::collections::HashMap;
+
+/// When team members may talk to each other through the bot.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RelayPolicy {
+    NightOnly,
+    Always,
+}
+
+/// How a tied verdict is decided.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TieBreak {
+    /// Nobody is executed.
+    NoExecution,
+    /// A coin is tossed.
+    Random,
+    /// The side that reached the final count first wins.
+    EarliestLeader,
+    /// Stalin's vote decides; if Stalin did not vote, nobody is executed.
+    Stalin,
+}
+
+/// Game settings.
+pub struct Settings {
+    pub relay: RelayPolicy,
+    /// Whether living Spies get a copy of other teams' relayed messages.
+    pub spy_intercepts: bool,
+    /// Whether a watching Spy learns the roles of visitors, not just their nicks.
+    pub spy_sees_roles: bool,
+    /// Seconds players have to join before a game starts.
+    pub start_time: u32,
+    /// Seconds each part of the day and the night lasts.
+    pub denunciation_time: u32,
+    pub trial_time: u32,
+    pub verdict_time: u32,
+    pub night_time: u32,
+    /// Denunciations needed to put a player on trial.
+    pub nominations: u8,
+    pub tie_break: TieBreak,
+    /// Days a living player may go without saying or doing anything before
+    /// they die of inactivity, with their role revealed like any death. On by
+    /// default; 0 turns it off.
+    pub idle_days: u8,
+    /// Whether roles are dealt so that the teams' ratings are close.
+    pub balance: bool,
+    /// Masks of the users that may change the game clock, as nick!user@host
+    /// in lowercase, where '*' stands for anything and '?' for any character.
+    pub admins: Vec<String>,
+}
+
+impl Settings {
+    /// Apply one configuration option. Options that are not game settings are ignored.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "team_chat" => {
+                self.relay = match value {
+                    "night" => RelayPolicy::NightOnly,
+                    "always" => RelayPolicy::Always,
+                    _ => return Err(format!("team_chat must be night or always, not {}", value)),
+                }
+            }
+            "spy_intercepts" => self.spy_intercepts = parse_bool(key, value)?,
+            "spy_sees_roles" => self.spy_sees_roles = parse_bool(key, value)?,
+            "start_time" => self.start_time = parse_seconds(key, value)?,
+            "denunciation_time" => self.denunciation_time = parse_seconds(key, value)?,
+            "trial_time" => self.trial_time = parse_seconds(key, value)?,
+            "verdict_time" => self.verdict_time = parse_seconds(key, value)?,
+            "night_time" => self.night_time = parse_seconds(key, value)?,
+            "nominations" => self.nominations = parse_number(key, value)?,
+            "idle_days" => {
+                self.idle_days = value.parse()
+                    .map_err(|_| format!("{} must be a number from 0 to 255, not {}", key, value))?
+            }
+            "balance_roles" => self.balance = parse_bool(key, value)?,
+            "admins" => {
+                // A nick alone can be taken by anybody: admins are known by host too.
+                let masks = value.split(',')
+                    .map(|n| n.trim().to_lowercase())
+                    .filter(|n| !n.is_empty())
+                    .collect::<Vec<_>>();
+                if let Some(m) = masks.iter().find(|m| !m.contains('!') || !m.contains('@')) {
+                    return Err(format!("admins must be nick!user@host masks, not {}", m));
+                }
+                self.admins = masks;
+            }
+            "tie_break" => {
+                self.tie_break = match value {
+                    "none" => TieBreak::NoExecution,
+                    "random" => TieBreak::Random,
+                    "earliest" => TieBreak::EarliestLeader,
+                    "stalin" => TieBreak::Stalin,
+                    _ => {
+                        return Err(format!("tie_break must be none, random, earliest or stalin, \
+                                            not {}",
+                                           value))
+                    }
+                }
+            }
+            _ => (),
+        }
+        return Ok(());
+    }
+
+    /// Whether a user, as nick!user@host, matches one of the admin masks,
+    /// ignoring case.
+    pub fn is_admin(&self, mask: &str) -> bool {
+        let mask = mask.to_lowercase();
+        self.admins.iter().any(|a| glob(a, &mask))
+    }
+
+    /// Read settings out of the bot's configuration options.
+    pub fn from_options(opts: &HashMap<String, String>) -> Result<Settings, String> {
+        let mut s = Settings::default();
+        for (k, v) in opts.iter() {
+            s.set(k, v)?;
+        }
+        return Ok(s);
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            relay: RelayPolicy::NightOnly,
+            spy_intercepts: false,
+            spy_sees_roles: false,
+            start_time: 60,
+            denunciation_time: 120,
+            trial_time: 45,
+            verdict_time: 45,
+            night_time: 90,
+            nominations: 2,
+            tie_break: TieBreak::NoExecution,
+            idle_days: 3,
+            balance: false,
+            admins: Vec::new(),
+        }
+    }
+}
+
+/// Whether text matches a pattern, where '*' stands for any run of characters
+/// and '?' for any one character.
+fn glob(pattern: &str, text: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let t = text.chars().collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    // The last '*' seen, and where in the text it was tried from.
+    let mut star = None;
+    while j < t.len() {
+        if i < p.len() && (p[i] == '?' || p[i] == t[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == '*' {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            // Let the '*' take one more character, and try again.
+            star = Some((si, sj + 1));
+            i = si + 1;
+            j = sj + 1;
+        } else {
+            return false;
+        }
+    }
+    return p[i..].iter().all(|&c| c == '*');
+}
+
+/// Parse a yes/no option.
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("{} must be true or false, not {}", key, value)),
+    }
+}
+
+/// Parse a positive number option.
+fn parse_number(key: &str, value: &str) -> Result<u8, String> {
+    match value.parse::<u8>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} must be a number from 1 to 255, not {}", key, value)),
+    }
+}
+
+/// Parse a positive number of seconds.
+fn parse_seconds(key: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} must be a number of seconds from 1 to {}, not {}",
+                         key, u32::MAX, value)),
+    }
+}
@@ -0,0 +1,251 @@
+//! Module: setup.
+//! This module contains game setups: which roles are dealt for each number of
+//! players, per-role balance overrides, and rules every lineup must follow.
+//! Setups are read from setup files and validated when loaded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use model::{Player, Role, Team};
+
+/// Built-in setups. The first one is the default.
+const DEFAULT_SETUPS: &str = include_str!("../setups/default.setup");
+
+/// Changes to a role's defaults. Unset fields keep what Player::new gives.
+#[derive(Default)]
+pub struct RoleOverride {
+    pub game_actions: Option<u8>,
+    pub turn_actions: Option<u8>,
+    pub real_team: Option<Team>,
+    pub apparent_team: Option<Team>,
+}
+
+/// What is made public about a player when they die.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Reveal {
+    Role,
+    /// The team they are really on.
+    Team,
+    /// The team they appear to be on, as the Chekist would see it.
+    ApparentTeam,
+    /// Nothing until the game is over.
+    Nothing,
+}
+
+/// A rule on how many of a role a lineup may have.
+pub enum Constraint {
+    Exactly(usize, Role),
+    AtMost(usize, Role),
+    AtLeast(usize, Role),
+    /// The role may only be dealt with at least this many players.
+    From(Role, usize),
+}
+
+impl Constraint {
+    /// Parse a rule: "exactly 1 Stalin", "at most 2 Saboteur",
+    /// "at least 1 Chekist" or "Spy from 9".
+    pub fn parse(s: &str) -> Result<Constraint, String> {
+        let w = s.split_whitespace().collect::<Vec<_>>();
+        let role = |name: &str| Role::from_name(name).ok_or(format!("Unknown role: {}", name));
+        let number = |n: &str| n.parse::<usize>().map_err(|_| format!("Not a number: {}", n));
+        match w.as_slice() {
+            ["exactly", n, r] => Ok(Constraint::Exactly(number(n)?, role(r)?)),
+            ["at", "most", n, r] => Ok(Constraint::AtMost(number(n)?, role(r)?)),
+            ["at", "least", n, r] => Ok(Constraint::AtLeast(number(n)?, role(r)?)),
+            [r, "from", n] => Ok(Constraint::From(role(r)?, number(n)?)),
+            _ => Err(format!("Unknown rule: {}", s)),
+        }
+    }
+
+    /// Check a lineup against the rule.
+    pub fn allows(&self, lineup: &[Role]) -> bool {
+        let count = |r: &Role| lineup.iter().filter(|&x| x == r).count();
+        match *self {
+            Constraint::Exactly(n, ref r) => count(r) == n,
+            Constraint::AtMost(n, ref r) => count(r) <= n,
+            Constraint::AtLeast(n, ref r) => count(r) >= n,
+            Constraint::From(ref r, n) => lineup.len() >= n || count(r) == 0,
+        }
+    }
+}
+
+/// A named setup.
+pub struct Setup {
+    pub name: String,
+    /// Roles to deal, by number of players.
+    pub lineups: BTreeMap<usize, Vec<Role>>,
+    pub overrides: HashMap<Role, RoleOverride>,
+    pub constraints: Vec<Constraint>,
+    pub reveal: Reveal,
+}
+
+impl Setup {
+    /// Create an empty setup.
+    fn new(name: &str) -> Setup {
+        let s = Setup {
+            name: name.to_string(),
+            lineups: BTreeMap::new(),
+            overrides: HashMap::new(),
+            constraints: Vec::new(),
+            reveal: Reveal::Role,
+        };
+        return s;
+    }
+
+    /// Parse every setup in the contents of a setup file, and validate them.
+    pub fn parse_all(contents: &str) -> Result<Vec<Setup>, String> {
+        let mut setups: Vec<Setup> = Vec::new();
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            let err = |e: String| format!("line {}: {}", n + 1, e);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim();
+                if name.is_empty() || setups.iter().any(|s| s.name == name) {
+                    return Err(err(format!("Bad or repeated setup name: {}", name)));
+                }
+                setups.push(Setup::new(name));
+                continue;
+            }
+            let setup = match setups.last_mut() {
+                Some(s) => s,
+                None => return Err(err("Setting outside of a setup.".to_string())),
+            };
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => return Err(err(format!("Not a setting: {}", line))),
+            };
+            setup.set(key, value).map_err(&err)?;
+        }
+        if setups.is_empty() {
+            return Err("No setups defined.".to_string());
+        }
+        for s in setups.iter() {
+            s.validate()?;
+        }
+        return Ok(setups);
+    }
+
+    /// Apply one "key = value" setting.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let parts = key.split('.').collect::<Vec<_>>();
+        match parts.as_slice() {
+            ["players", count] => {
+                let count = count.parse::<usize>()
+                    .map_err(|_| format!("Not a player count: {}", count))?;
+                let mut roles = Vec::new();
+                for name in value.split(',') {
+                    let name = name.trim();
+                    roles.push(Role::from_name(name).ok_or(format!("Unknown role: {}", name))?);
+                }
+                if roles.len() != count {
+                    return Err(format!("{} roles listed for {} players.", roles.len(), count));
+                }
+                self.lineups.insert(count, roles);
+            }
+            ["role", role, field] => {
+                let role = Role::from_name(role).ok_or(format!("Unknown role: {}", role))?;
+                let number = || value.parse::<u8>().map_err(|_| format!("Not a number: {}", value));
+                let team = || Team::from_name(value).ok_or(format!("Unknown team: {}", value));
+                let o = self.overrides.entry(role).or_default();
+                match *field {
+                    "game_actions" => o.game_actions = Some(number()?),
+                    "turn_actions" => o.turn_actions = Some(number()?),
+                    "team" => o.real_team = Some(team()?),
+                    "apparent_team" => o.apparent_team = Some(team()?),
+                    _ => return Err(format!("Unknown role setting: {}", field)),
+                }
+            }
+            ["require"] => self.constraints.push(Constraint::parse(value)?),
+            ["reveal"] => {
+                self.reveal = match value {
+                    "role" => Reveal::Role,
+                    "team" => Reveal::Team,
+                    "apparent_team" => Reveal::ApparentTeam,
+                    "none" => Reveal::Nothing,
+                    _ => return Err(format!("Unknown reveal: {}", value)),
+                }
+            }
+            _ => return Err(format!("Unknown setting: {}", key)),
+        }
+        return Ok(());
+    }
+
+    /// Check that the setup can be played and that every lineup follows its rules.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.lineups.is_empty() {
+            return Err(format!("{}: no lineups.", self.name));
+        }
+        for (count, lineup) in self.lineups.iter() {
+            if *count < 2 {
+                return Err(format!("{}: at least two players are needed.", self.name));
+            }
+            for (i, c) in self.constraints.iter().enumerate() {
+                if !c.allows(lineup) {
+                    return Err(format!("{}: the lineup for {} players breaks rule {}.",
+                                       self.name,
+                                       count,
+                                       i + 1));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// Load a named setup out of a setup file.
+    pub fn load(path: &str, name: &str) -> Result<Setup, String> {
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .map_err(|e| format!("{}: {}", path, e))?;
+        let setups = Setup::parse_all(&contents).map_err(|e| format!("{}: {}", path, e))?;
+        match setups.into_iter().find(|s| s.name == name) {
+            Some(s) => Ok(s),
+            None => Err(format!("{}: no setup called {}.", path, name)),
+        }
+    }
+
+    /// A built-in setup, by name.
+    pub fn builtin(name: &str) -> Option<Setup> {
+        Setup::parse_all(DEFAULT_SETUPS).unwrap().into_iter().find(|s| s.name == name)
+    }
+
+    /// Roles to deal for a number of players, if the setup supports it.
+    pub fn lineup(&self, players: usize) -> Option<&Vec<Role>> {
+        self.lineups.get(&players)
+    }
+
+    /// The most players any lineup of the setup is for.
+    pub fn max_players(&self) -> usize {
+        self.lineups.keys().next_back().cloned().unwrap_or(0)
+    }
+
+    /// Create a player with a role, applying the setup's overrides.
+    pub fn new_player(&self, nick: String, r: Role) -> Player {
+        let mut p = Player::new(nick, r);
+        if let Some(o) = self.overrides.get(&r) {
+            if let Some(n) = o.game_actions {
+                p.game_actions = n;
+            }
+            if let Some(n) = o.turn_actions {
+                p.turn_actions = n;
+            }
+            if let Some(t) = o.real_team {
+                p.real_team = t;
+            }
+            if let Some(t) = o.apparent_team {
+                p.apparent_team = t;
+            }
+        }
+        return p;
+    }
+}
+
+impl Default for Setup {
+    /// The first built-in setup.
+    fn default() -> Setup {
+        Setup::parse_all(DEFAULT_SETUPS).unwrap().remove(0)
+    }
+}
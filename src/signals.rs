@@ -0,0 +1,63 @@
+//! Module: signals.
+//! This module turns SIGINT and SIGTERM into a quit event, so the bot shuts
+//! down the same way as when its owner tells it to. A second signal stops the
+//! bot at once, in case the shutdown hangs.
+
+use std::os::raw::c_int;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use logging::{self, Context};
+use model::GameEvent;
+
+#[cfg(unix)]
+const SIGINT: c_int = 2;
+#[cfg(unix)]
+const SIGTERM: c_int = 15;
+
+/// Number of shutdown signals received so far.
+static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+/// Count a signal. A signal handler can safely do little more than this.
+#[cfg(unix)]
+extern "C" fn handle(_: c_int) {
+    RECEIVED.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Send a quit event on the first SIGINT or SIGTERM, and exit on the second.
+pub fn on_shutdown(tx: Sender<GameEvent>) {
+    #[cfg(unix)]
+    unsafe {
+        signal(SIGINT, handle);
+        signal(SIGTERM, handle);
+    }
+    let _ = thread::spawn(move || {
+        let mut sent = false;
+        loop {
+            thread::sleep(Duration::from_millis(100));
+            match RECEIVED.load(Ordering::SeqCst) {
+                0 => (),
+                1 => {
+                    if !sent {
+                        logging::info(&Context::default(), "shutdown signal received");
+                        sent = true;
+                        if tx.send(GameEvent::Quit).is_err() {
+                            return;
+                        }
+                    }
+                }
+                _ => {
+                    logging::warn(&Context::default(), "second shutdown signal, exiting now");
+                    process::exit(130);
+                }
+            }
+        }
+    });
+}
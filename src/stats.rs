@@ -0,0 +1,278 @@
+//! Module: stats.
+//! This module keeps the record of every finished game, per player, in a
+//! plain text file, and works out player statistics, ratings and the
+//! leaderboard from it.
+//!
+//! Ratings are Elo ratings, replayed from the records: after each game, every
+//! winner gains and every loser loses the same amount, which is larger the
+//! stronger the losing side was against the winning one.
+//!
+//! Each line of the file is one player in one game:
+//! "<game> <nick> <role> <team> <won|lost> <survived|died|executed>".
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use model::{Role, Team};
+
+/// Rating of a player who has not played yet.
+pub const START_RATING: f64 = 1500.0;
+/// Most rating points a game can change.
+const K_FACTOR: f64 = 32.0;
+
+/// How a player's game ended for them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fate {
+    Survived,
+    Died,
+    /// Sentenced by the tribunal.
+    Executed,
+}
+
+impl Fate {
+    /// Name of the fate in the theme and the stats file.
+    pub fn key(&self) -> &'static str {
+        match *self {
+            Fate::Survived => "survived",
+            Fate::Died => "died",
+            Fate::Executed => "executed",
+        }
+    }
+}
+
+/// One player in one finished game.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub game: u32,
+    pub nick: String,
+    /// The role and team they ended the game with.
+    pub role: Role,
+    pub team: Team,
+    pub won: bool,
+    pub fate: Fate,
+}
+
+impl Record {
+    /// Parse a line of the stats file.
+    fn parse(line: &str) -> Result<Record, String> {
+        let w = line.split_whitespace().collect::<Vec<_>>();
+        match w.as_slice() {
+            [game, nick, role, team, result, fate] => {
+                let r = Record {
+                    game: game.parse().map_err(|_| format!("Not a game number: {}", game))?,
+                    nick: nick.to_string(),
+                    role: Role::from_name(role).ok_or(format!("Unknown role: {}", role))?,
+                    team: Team::from_name(team).ok_or(format!("Unknown team: {}", team))?,
+                    won: match *result {
+                        "won" => true,
+                        "lost" => false,
+                        _ => return Err(format!("Unknown result: {}", result)),
+                    },
+                    fate: match *fate {
+                        "survived" => Fate::Survived,
+                        "died" => Fate::Died,
+                        "executed" => Fate::Executed,
+                        _ => return Err(format!("Unknown fate: {}", fate)),
+                    },
+                };
+                Ok(r)
+            }
+            _ => Err(format!("Not a record: {}", line)),
+        }
+    }
+
+    /// The record as a line of the stats file.
+    fn to_line(&self) -> String {
+        let result = match self.won {
+            true => "won",
+            false => "lost",
+        };
+        format!("{} {} {:?} {:?} {} {}",
+                self.game,
+                self.nick,
+                self.role,
+                self.team,
+                result,
+                self.fate.key())
+    }
+}
+
+/// A player's statistics, over every game they played.
+#[derive(Default)]
+pub struct Summary {
+    pub games: u32,
+    pub wins: u32,
+    pub survived: u32,
+    pub executed: u32,
+    pub rating: f64,
+    /// Wins and losses, by the team played for.
+    pub teams: Vec<(Team, u32, u32)>,
+    /// Roles held, and how many times, most frequent first.
+    pub roles: Vec<(Role, u32)>,
+}
+
+/// The records of every finished game.
+#[derive(Default)]
+pub struct Stats {
+    /// File the records are kept in. Without one, they only live in memory.
+    pub path: Option<String>,
+    pub records: Vec<Record>,
+    /// Current rating of each player, by lowercase nick.
+    pub ratings: HashMap<String, f64>,
+}
+
+impl Stats {
+    /// Load the records kept in a file. A missing file is an empty record.
+    pub fn load(path: &str) -> Result<Stats, String> {
+        let mut contents = String::new();
+        match File::open(path) {
+            Ok(mut f) => {
+                f.read_to_string(&mut contents).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        }
+        let mut s = Stats {
+            path: Some(path.to_string()),
+            ..Stats::default()
+        };
+        let mut game = Vec::new();
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let r = Record::parse(line)
+                .map_err(|e| format!("{}: line {}: {}", path, n + 1, e))?;
+            // Records of a game are kept together; rate each game as it ends.
+            if game.last().is_some_and(|l: &Record| l.game != r.game) {
+                s.rate(&game);
+                s.records.append(&mut game);
+            }
+            game.push(r);
+        }
+        s.rate(&game);
+        s.records.extend(game);
+        return Ok(s);
+    }
+
+    /// Number the next game will be recorded under.
+    pub fn next_game(&self) -> u32 {
+        self.records.iter().map(|r| r.game).max().unwrap_or(0) + 1
+    }
+
+    /// Add a finished game, and append it to the file if there is one.
+    pub fn add_game(&mut self, records: Vec<Record>) -> Result<(), String> {
+        if let Some(ref path) = self.path {
+            let lines = records.iter().map(|r| r.to_line() + "\n").collect::<String>();
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| f.write_all(lines.as_bytes()))
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        self.rate(&records);
+        self.records.extend(records);
+        return Ok(());
+    }
+
+    /// Current rating of a player.
+    pub fn rating(&self, nick: &str) -> f64 {
+        *self.ratings.get(&nick.to_lowercase()).unwrap_or(&START_RATING)
+    }
+
+    /// Update ratings with the outcome of a game, weighing the average rating
+    /// of the winners against that of the losers.
+    fn rate(&mut self, game: &[Record]) {
+        let average = |won: bool| {
+            let v = game.iter().filter(|r| r.won == won).map(|r| self.rating(&r.nick));
+            let v = v.collect::<Vec<_>>();
+            match v.is_empty() {
+                true => None,
+                false => Some(v.iter().sum::<f64>() / v.len() as f64),
+            }
+        };
+        let (winners, losers) = match (average(true), average(false)) {
+            (Some(w), Some(l)) => (w, l),
+            _ => return,
+        };
+        let expected = 1.0 / (1.0 + 10f64.powf((losers - winners) / 400.0));
+        let change = K_FACTOR * (1.0 - expected);
+        for r in game.iter() {
+            let change = match r.won {
+                true => change,
+                false => -change,
+            };
+            let rating = self.rating(&r.nick) + change;
+            self.ratings.insert(r.nick.to_lowercase(), rating);
+        }
+    }
+
+    /// Records of a player, oldest first. Nicks are matched ignoring case.
+    fn of(&self, nick: &str) -> Vec<&Record> {
+        let nick = nick.to_lowercase();
+        self.records.iter().filter(|r| r.nick.to_lowercase() == nick).collect()
+    }
+
+    /// Statistics of a player, if they have played.
+    pub fn summary(&self, nick: &str) -> Option<Summary> {
+        let records = self.of(nick);
+        if records.is_empty() {
+            return None;
+        }
+        let mut s = Summary {
+            rating: self.rating(nick),
+            ..Summary::default()
+        };
+        let mut roles: HashMap<Role, u32> = HashMap::new();
+        for r in records.iter() {
+            s.games += 1;
+            if r.won {
+                s.wins += 1;
+            }
+            match r.fate {
+                Fate::Survived => s.survived += 1,
+                Fate::Executed => s.executed += 1,
+                Fate::Died => (),
+            }
+            match s.teams.iter().position(|&(t, _, _)| t == r.team) {
+                Some(i) => {
+                    match r.won {
+                        true => s.teams[i].1 += 1,
+                        false => s.teams[i].2 += 1,
+                    }
+                }
+                None => s.teams.push((r.team, r.won as u32, !r.won as u32)),
+            }
+            *roles.entry(r.role).or_insert(0) += 1;
+        }
+        s.roles = roles.into_iter().collect();
+        s.roles.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.key().cmp(b.0.key())));
+        return Some(s);
+    }
+
+    /// The players with the most wins, as (nick, wins, games). Ties go to
+    /// whoever played fewer games, then by nick.
+    pub fn top(&self, count: usize) -> Vec<(String, u32, u32)> {
+        let mut players: HashMap<String, (String, u32, u32)> = HashMap::new();
+        for r in self.records.iter() {
+            let e = players.entry(r.nick.to_lowercase())
+                .or_insert((r.nick.clone(), 0, 0));
+            e.1 += r.won as u32;
+            e.2 += 1;
+        }
+        let mut top = players.into_values().collect::<Vec<_>>();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)).then(a.0.cmp(&b.0)));
+        top.truncate(count);
+        return top;
+    }
+
+    /// A player's most recent games, newest first.
+    pub fn history(&self, nick: &str, count: usize) -> Vec<&Record> {
+        let mut records = self.of(nick);
+        records.reverse();
+        records.truncate(count);
+        return records;
+    }
+}
@@ -1,50 +1,48 @@
-use super::*;
+use std::collections::HashMap;
+use std::time::Duration;
+use clock::Clock;
+use logging::{self, Context, Level, Logger};
+use model::*;
+use night::ActionKind;
+use rng::Rng;
+use settings::{RelayPolicy, Settings, TieBreak};
+use setup::{Reveal, Setup};
+use stats::{Fate, Record, Stats};
+use theme::Theme;
+use view::Seen;
 
 /// Test Game construction.
 #[test]
 fn test_new_game() {
-    let g = Game::new(&"#test_channel".to_string());
+    let g = Game::new("#test_channel");
     assert!(g.channel == "#test_channel");
-    assert!(match g.gun {
-                Gun::Loaded => true,
-                _ => false,
-            });
-    assert!(match g.phase {
-                Phase::Inactive => true,
-                _ => false,
-            });
+    assert!(matches!(g.phase, Phase::Inactive));
 }
 
 /// Test a Join event on an inactive game.
 #[test]
 fn test_join_event() {
-    let g = Game::new(&"#test_channel".to_string());
+    let g = Game::new("#test_channel");
     let e = GameEvent::Join("test_nick".to_string());
     let g2 = g.process(e);
     assert!(match g2.players {
                 Participants::Players(_) => false,
                 Participants::Joiners(ref v) => v.len() == 1 && v[0] == "test_nick",
             });
-    assert!(match g2.phase {
-                Phase::Starting(_) => true,
-                _ => false,
-            });
+    assert!(matches!(g2.phase, Phase::Starting));
 }
 
 /// Test two join events.
 #[test]
 fn test_two_joins() {
-    let g = Game::new(&"#test_channel".to_string());
+    let g = Game::new("#test_channel");
     let e1 = GameEvent::Join("test_nick1".to_string());
     let e2 = GameEvent::Join("test_nick2".to_string());
     let g2 = g.process(e1);
     let g3 = g2.process(e2);
-    assert!(match g3.phase {
-                Phase::Starting(_) => true,
-                _ => false,
-            });
+    assert!(matches!(g3.phase, Phase::Starting));
     match g3.players {
-        Participants::Players(_) => assert!(false),
+        Participants::Players(_) => unreachable!(),
         Participants::Joiners(ref v) => {
             assert!(v.len() == 2);
             assert!(v.contains(&"test_nick1".to_string()));
@@ -56,7 +54,7 @@ fn test_two_joins() {
 /// Test an attempt to join twice with the same nick.
 #[test]
 fn test_join_twice() {
-    let mut g = Game::new(&"#test_channel".to_string());
+    let mut g = Game::new("#test_channel");
     let e = GameEvent::Join("test_nick".to_string());
     let e2 = GameEvent::Join("test_nick".to_string());
     g = g.process(e);
@@ -71,7 +69,7 @@ fn test_join_twice() {
 #[test]
 fn test_leave() {
     // Create a Game and a join and leave Event for the same nick.
-    let mut g = Game::new(&"#test_channel".to_string());
+    let mut g = Game::new("#test_channel");
     let e = GameEvent::Join("test_nick".to_string());
     let e2 = GameEvent::Leave("test_nick".to_string());
     // Process events.
@@ -79,14 +77,11 @@ fn test_leave() {
     g = g.process(e2);
     // Assertions:
     // When the only player of a game that is starting quits, the game goes inactive.
-    assert!(match g.phase {
-                Phase::Inactive => true,
-                _ => false,
-            });
+    assert!(matches!(g.phase, Phase::Inactive));
     // And the player list is empty.
     assert!(match g.players {
                 Participants::Players(_) => false,
-                Participants::Joiners(ref v) => v.len() == 0,
+                Participants::Joiners(ref v) => v.is_empty(),
             });
 }
 
@@ -94,7 +89,7 @@ fn test_leave() {
 // Testing two joins, followed by two leaves.
 fn test_two_joins_two_leaves() {
     // Create a Game and two join and leave Events.
-    let mut g = Game::new(&"#test_channel".to_string());
+    let mut g = Game::new("#test_channel");
     let e = GameEvent::Join("test_nick".to_string());
     let e2 = GameEvent::Join("test_nick2".to_string());
     let e3 = GameEvent::Leave("test_nick".to_string());
@@ -105,10 +100,7 @@ fn test_two_joins_two_leaves() {
     g = g.process(e3);
     // Assertions:
     // After two joins and one leave, we're on Starting phase.
-    assert!(match g.phase {
-                Phase::Starting(_) => true,
-                _ => false,
-            });
+    assert!(matches!(g.phase, Phase::Starting));
     // And there's one player.
     assert!(match g.players {
                 Participants::Players(_) => false,
@@ -118,12 +110,1564 @@ fn test_two_joins_two_leaves() {
     g = g.process(e4);
     // Assertions:
     // Back to the Starting condition with no players.
-    assert!(match g.phase {
-                Phase::Inactive => true,
-                _ => false,
-            });
+    assert!(matches!(g.phase, Phase::Inactive));
     assert!(match g.players {
                 Participants::Players(_) => false,
-                Participants::Joiners(ref v) => v.len() == 0,
+                Participants::Joiners(ref v) => v.is_empty(),
             });
 }
+
+/// Test template rendering, including nicks that look like placeholders.
+#[test]
+fn test_theme_render() {
+    let t = Theme::parse("test", "greet = Hello {nick}, you are {role}.").unwrap();
+    assert!(t.render("greet", &[("nick", "{role}"), ("role", "a worker")]) ==
+            "Hello {role}, you are a worker.");
+    // Keys missing from a custom theme fall back to the Soviet pack.
+    assert!(t.get("role.stalin") == "general secretary of the Party");
+    assert!(Theme::parse("broken", "no equals sign here").is_err());
+}
+
+/// Test that the player printout is worded by the theme.
+#[test]
+fn test_player_to_string() {
+    let p = Player::new("test_nick".to_string(), Role::Chekist);
+    let soviet = Theme::default();
+    let neutral = Theme::load("neutral", "en").unwrap();
+    assert!(p.to_string(&soviet)
+                .starts_with("You, test_nick, are a uncompromising CHEKA member"));
+    assert!(p.to_string(&neutral).starts_with("You, test_nick, are a detective"));
+    assert!(p.to_string(&neutral).contains("You have 8 game actions"));
+}
+
+/// Test switching themes: allowed while inactive, refused during a game.
+#[test]
+fn test_theme_event() {
+    let mut g = Game::new("#test_channel");
+    g = g.process(GameEvent::Theme("test_nick".to_string(), "neutral".to_string(), None));
+    assert!(g.theme.name == "neutral");
+    g = g.process(GameEvent::Theme("test_nick".to_string(),
+                                   "../etc/passwd".to_string(),
+                                   None));
+    assert!(g.theme.name == "neutral");
+    g = g.process(GameEvent::Join("test_nick".to_string()));
+    assert!(g.pending.last().unwrap().msg[0].content == "test_nick is starting a new game!");
+    g = g.process(GameEvent::Theme("test_nick".to_string(), "soviet".to_string(), None));
+    assert!(g.theme.name == "neutral");
+}
+
+/// Test plural forms in English and Russian.
+#[test]
+fn test_plural_forms() {
+    let en = Theme::default();
+    let ru = Theme::load("soviet", "ru").unwrap();
+    assert!(en.count("count.game_actions", 1) == "1 game action");
+    assert!(en.count("count.game_actions", 8) == "8 game actions");
+    assert!(ru.count("count.game_actions", 1) == "1 игровое действие");
+    assert!(ru.count("count.game_actions", 3) == "3 игровых действия");
+    assert!(ru.count("count.game_actions", 8) == "8 игровых действий");
+    assert!(ru.count("count.game_actions", 11) == "11 игровых действий");
+    assert!(ru.count("count.game_actions", 21) == "21 игровое действие");
+    assert!(Theme::load("soviet", "xx").is_err());
+}
+
+/// Test private locales: picked per player, English for everybody else.
+#[test]
+fn test_locale_event() {
+    let mut g = Game::new("#test_channel");
+    g = g.process(GameEvent::Locale("ivan".to_string(), "ru".to_string()));
+    g = g.process(GameEvent::Locale("john".to_string(), "xx".to_string()));
+    assert!(g.text_for("ivan").locale == "ru");
+    assert!(g.text_for("john").locale == "en");
+    assert!(g.pending[1].msg[0].content == "There is no xx translation.");
+    let p = Player::new("ivan".to_string(), Role::Stalin);
+    assert!(p.to_string(g.text_for("ivan")).contains("1 игровое действие"));
+    // Switching the channel theme keeps the player's locale.
+    g = g.process(GameEvent::Theme("john".to_string(), "neutral".to_string(), None));
+    assert!(g.text_for("ivan").name == "neutral" && g.text_for("ivan").locale == "ru");
+}
+
+/// Test that setups are validated when loaded.
+#[test]
+fn test_setup_validation() {
+    let ok = "[tiny]\nplayers.3 = Stalin, Worker, Saboteur\nrequire = exactly 1 Stalin\n\
+              role.worker.game_actions = 2\nrole.worker.team = opposition";
+    let setups = Setup::parse_all(ok).unwrap();
+    assert!(setups[0].name == "tiny");
+    let p = setups[0].new_player("test_nick".to_string(), Role::Worker);
+    assert!(p.game_actions == 2 && p.real_team == Team::Opposition);
+    // Wrong number of roles, unknown roles and broken rules are all refused.
+    assert!(Setup::parse_all("[a]\nplayers.3 = Stalin, Worker").is_err());
+    assert!(Setup::parse_all("[a]\nplayers.2 = Stalin, Tsar").is_err());
+    assert!(Setup::parse_all("[a]\nplayers.2 = Worker, Worker\nrequire = exactly 1 Stalin")
+                .is_err());
+    assert!(Setup::parse_all("[a]\nplayers.2 = Spy, Worker\nrequire = Spy from 9").is_err());
+    assert!(Setup::parse_all("players.2 = Stalin, Worker").is_err());
+    // The built-in setups are valid.
+    assert!(Setup::default().name == "classic");
+    assert!(Setup::builtin("purge").is_some());
+}
+
+/// Test that a game with enough joiners starts with the setup's lineup.
+#[test]
+fn test_game_start() {
+    let mut g = Game::new("#test_channel");
+    g.rng = Rng::new(1);
+    g.clock = Clock::manual();
+    for i in 0..5 {
+        g = g.process(GameEvent::Join(format!("test_nick{}", i)));
+    }
+    while let Phase::Starting = g.phase {
+        g = tick(g.clean_up());
+    }
+    assert!(g.phase == Phase::Denunciation(1));
+    match g.players {
+        Participants::Joiners(_) => unreachable!(),
+        Participants::Players(ref v) => {
+            assert!(v.len() == 5);
+            assert!(v.iter().filter(|p| p.role == Role::Stalin).count() == 1);
+            assert!(v.iter().filter(|p| p.role == Role::Saboteur).count() == 1);
+        }
+    }
+    // One public announcement, every player hears their role privately,
+    // and the first day is called to session.
+    assert!(g.pending[0].msg.len() == 7);
+}
+
+/// Test that a game with too few joiners is cancelled.
+#[test]
+fn test_game_cancelled() {
+    let mut g = Game::new("#test_channel");
+    g.clock = Clock::manual();
+    g = g.process(GameEvent::Join("test_nick".to_string()));
+    while let Phase::Starting = g.phase {
+        g = tick(g);
+    }
+    assert!(matches!(g.phase, Phase::Inactive));
+    assert!(g.pending.last().unwrap().msg[0].content.starts_with("Not enough comrades"));
+}
+
+/// Test that a game with more joiners than the largest lineup is cancelled
+/// for that reason, not for a lack of players.
+#[test]
+fn test_game_too_many() {
+    let mut g = Game::new("#test_channel");
+    g.clock = Clock::manual();
+    for i in 0..g.setup.max_players() + 1 {
+        g = g.process(GameEvent::Join(format!("nick{}", i)));
+    }
+    while let Phase::Starting = g.phase {
+        g = tick(g);
+    }
+    assert!(matches!(g.phase, Phase::Inactive));
+    let text = &g.pending.last().unwrap().msg[0].content;
+    assert!(text.starts_with("Too many comrades"));
+    assert!(text.contains("at most 12"));
+}
+
+/// Test the !setup listing.
+#[test]
+fn test_setup_event() {
+    let mut g = Game::new("#test_channel");
+    g = g.process(GameEvent::Setup("test_nick".to_string()));
+    let msgs = &g.pending[0].msg;
+    assert!(msgs[0].content == "The classic setup.");
+    assert!(msgs.iter().any(|m| m.content == "Spy only with 9 or more players."));
+    assert!(msgs.iter().all(|m| match m.recipients {
+                                    Recipients::Nicks(ref v) => v[0] == "test_nick",
+                                    _ => false,
+                                }));
+}
+
+/// Build a game that is already being played, with the given nicks and roles.
+fn playing_game(roles: &[(&str, Role)]) -> Game {
+    let mut g = Game::new("#test_channel");
+    g.rng = Rng::new(1);
+    g.players = Participants::Players(roles.iter()
+                                          .map(|&(n, r)| Player::new(n.to_string(), r))
+                                          .collect());
+    g.phase = Phase::Denunciation(1);
+    g.clock = Clock::manual();
+    g
+}
+
+/// Test that IRC formatting and control codes are stripped from player text.
+#[test]
+fn test_sanitize() {
+    assert!(sanitize("\x02bold\x02 \x0304,12red\x03 plain\x0f", 100) == "bold red plain");
+    assert!(sanitize("\x0312,3x", 100) == "x");
+    assert!(sanitize("two\r\nlines", 100) == "two  lines");
+    assert!(sanitize("a", 0).is_empty());
+    assert!(sanitize(&"x".repeat(500), MAX_WILL_LEN).len() == MAX_WILL_LEN);
+}
+
+/// Test writing a will and having it read at death.
+#[test]
+fn test_will_published_on_death() {
+    let mut g = playing_game(&[("ivan", Role::Worker), ("olga", Role::Saboteur)]);
+    g = g.process(GameEvent::Will("ivan".to_string(), "first draft".to_string()));
+    g = g.process(GameEvent::Will("ivan".to_string(), "It was \x02olga\x02.".to_string()));
+    g = g.process(GameEvent::Will("stranger".to_string(), "hello".to_string()));
+    assert!(g.pending[2].msg[0].content == "You are not taking part in a game.");
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("ivan", DeathCause::NightKill, &mut gr);
+    assert!(gr.msg.len() == 3);
+    assert!(gr.msg[0].content == "At dawn, ivan is found dead in a ditch.");
+    assert!(gr.msg[2].content.ends_with("testament is found: It was olga."));
+    // The dead cannot edit their will, nor die twice.
+    g = g.process(GameEvent::Will("ivan".to_string(), "changed".to_string()));
+    assert!(g.pending.last().unwrap().msg[0].content ==
+            "The archive does not accept papers from the dead.");
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("ivan", DeathCause::NightKill, &mut gr);
+    assert!(gr.msg.is_empty());
+    // Without a will, only the death and the role are announced.
+    g.kill("olga", DeathCause::Tribunal, &mut gr);
+    assert!(gr.msg.len() == 2);
+}
+
+/// Test the team chat relay, its phase policy and Spy interception.
+#[test]
+fn test_team_chat() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("lev", Role::Mastermind),
+                               ("kim", Role::Spy)]);
+    let chat = |who: &str| GameEvent::TeamChat(who.to_string(), "kill ivan".to_string());
+    // By default, conspiracy waits for the night.
+    g = g.process(chat("olga"));
+    assert!(g.pending[0].msg[0].content == "Conspirators only meet under cover of night.");
+    g.phase = Phase::Night(1);
+    g = g.process(chat("olga"));
+    match g.pending[1].msg[0].recipients {
+        Recipients::Nicks(ref v) => assert!(v == &vec!["lev".to_string()]),
+        _ => unreachable!(),
+    }
+    assert!(g.pending[1].msg.len() == 1);
+    // Workers have nobody to talk to.
+    g = g.process(chat("ivan"));
+    assert!(g.pending[2].msg[0].content == "You have no comrades to conspire with.");
+    // With interception on, the Spy gets an anonymous copy.
+    g.settings.spy_intercepts = true;
+    g.settings.relay = RelayPolicy::Always;
+    g.phase = Phase::Denunciation(2);
+    g = g.process(chat("lev"));
+    assert!(g.pending[3].msg.len() == 2);
+    assert!(g.pending[3].msg[1].content == "An intercepted transmission: kill ivan");
+    // Dead teammates hear nothing.
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("lev", DeathCause::Tribunal, &mut gr);
+    g = g.process(chat("olga"));
+    assert!(g.pending[4].msg[0].content == "You have no comrades to conspire with.");
+}
+
+/// Test reading settings from configuration options.
+#[test]
+fn test_settings_from_options() {
+    let mut opts = HashMap::new();
+    opts.insert("team_chat".to_string(), "always".to_string());
+    opts.insert("spy_intercepts".to_string(), "yes".to_string());
+    opts.insert("theme".to_string(), "neutral".to_string());
+    let s = Settings::from_options(&opts).unwrap();
+    assert!(s.relay == RelayPolicy::Always && s.spy_intercepts);
+    opts.insert("team_chat".to_string(), "sometimes".to_string());
+    assert!(Settings::from_options(&opts).is_err());
+}
+
+/// Test that phases may last longer than 255 seconds, but not no time at all.
+#[test]
+fn test_long_phases() {
+    let mut s = Settings::default();
+    assert!(s.set("night_time", "600").is_ok() && s.night_time == 600);
+    assert!(s.set("start_time", "86400").is_ok() && s.start_time == 86400);
+    assert!(s.set("trial_time", "0").is_err() && s.set("trial_time", "4294967296").is_err());
+    let mut g = Game::new("#test_channel");
+    g.clock = Clock::manual();
+    g.settings = s;
+    g = g.process(GameEvent::Join("ivan".to_string()));
+    assert!(g.remaining() == 86400);
+}
+
+/// Test that admins are known by their nick!user@host, and not by nick alone.
+#[test]
+fn test_admin_masks() {
+    let mut s = Settings::default();
+    assert!(s.set("admins", "Kim!*@*.Kremlin.su, lev!lev@10.0.0.?, *!stalin@2001:db8::1").is_ok());
+    assert!(s.is_admin("kim!kim@office.kremlin.su") && s.is_admin("KIM!k@a.b.kremlin.su"));
+    assert!(!s.is_admin("kim!kim@kremlin.su.evil.com") && !s.is_admin("kim"));
+    assert!(s.is_admin("lev!lev@10.0.0.7") && !s.is_admin("lev!lev@10.0.0.70"));
+    assert!(s.is_admin("anyone!stalin@2001:db8::1") && !s.is_admin("ivan!ivan@2001:db8::2"));
+    assert!(s.set("admins", "kim, lev!lev@10.0.0.1").is_err());
+    assert!(s.set("admins", "kim@kremlin.su").is_err());
+    assert!(s.set("admins", "").is_ok() && s.admins.is_empty());
+}
+
+/// Let a second pass on the game's clock.
+fn tick(mut g: Game) -> Game {
+    g.clock.advance(Duration::from_secs(1));
+    g.process(GameEvent::Tick)
+}
+
+/// Tick a game until its phase changes.
+fn tick_phase(mut g: Game) -> Game {
+    let phase = g.phase.clone();
+    while g.phase == phase {
+        g = tick(g);
+    }
+    g
+}
+
+/// Test a full day: denunciation, trial, verdict and execution.
+#[test]
+fn test_show_trial() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("lev", Role::Mastermind),
+                               ("kim", Role::Stalin)]);
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "olga".to_string()));
+    assert!(g.phase == Phase::Denunciation(1));
+    // Changing one's mind does not count twice.
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "lev".to_string()));
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "olga".to_string()));
+    assert!(g.phase == Phase::Denunciation(1));
+    g = g.process(GameEvent::Denounce("kim".to_string(), "nobody".to_string()));
+    g = g.process(GameEvent::Denounce("kim".to_string(), "olga".to_string()));
+    assert!(g.phase == Phase::Trial(1, "olga".to_string()));
+    // Only the accused may speak, and nobody votes yet.
+    g = g.clean_up();
+    g = g.process(GameEvent::Msg("olga".to_string(), "I am innocent!".to_string()));
+    g = g.process(GameEvent::Msg("lev".to_string(), "She is!".to_string()));
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    assert!(g.pending.len() == 2);
+    assert!(g.pending[0].msg[0].content.starts_with("Silence in the court!"));
+    g = tick_phase(g);
+    assert!(g.phase == Phase::Verdict(1, "olga".to_string()));
+    g = g.process(GameEvent::Vote("olga".to_string(), Ballot::Innocent));
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    g = g.process(GameEvent::Vote("lev".to_string(), Ballot::Innocent));
+    assert!(g.votes.len() == 2);
+    g = g.clean_up();
+    // Two of the three voters is a majority, so the verdict is given at once.
+    g = g.process(GameEvent::Vote("kim".to_string(), Ballot::Guilty));
+    assert!(g.phase == Phase::Night(1));
+    assert!(!g.is_alive("olga"));
+    let texts = g.pending
+        .last()
+        .unwrap()
+        .msg
+        .iter()
+        .map(|m| m.content.clone())
+        .collect::<Vec<_>>();
+    assert!(texts[0] == "Guilty: ivan, kim. Innocent: lev. Abstained: nobody.");
+    assert!(texts[1] == "olga is found guilty, 2 votes to 1.");
+    assert!(texts[2] == "By sentence of the People's Tribunal, olga is shot.");
+    match g.pending.last().unwrap().event {
+        GameEvent::Tribunal(ref t) => assert!(t.executed && t.guilty.len() == 2),
+        _ => unreachable!(),
+    }
+    // The night passes, and a new day begins with a clean slate.
+    g = tick_phase(g);
+    assert!(g.phase == Phase::Denunciation(2));
+    assert!(g.nominations.is_empty());
+}
+
+/// Test a day where nobody is brought to trial.
+#[test]
+fn test_no_trial() {
+    let mut g = playing_game(&[("ivan", Role::Worker), ("olga", Role::Saboteur)]);
+    g = tick_phase(g);
+    assert!(g.phase == Phase::Night(1));
+}
+
+/// Test that a majority voting to try nobody ends the day at once.
+#[test]
+fn test_abstain_ends_day() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin)]);
+    g = g.process(GameEvent::Abstain("ivan".to_string()));
+    assert!(g.phase == Phase::Denunciation(1));
+    g = g.process(GameEvent::Abstain("olga".to_string()));
+    assert!(g.phase == Phase::Night(1));
+}
+
+/// Build a tied verdict on olga: ivan votes guilty first, then kim innocent,
+/// and lev abstains.
+fn tied_verdict(rule: TieBreak) -> Game {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin),
+                               ("lev", Role::Chekist)]);
+    g.settings.tie_break = rule;
+    g.phase = Phase::Verdict(1, "olga".to_string());
+    g.set_remaining(1);
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    g = g.process(GameEvent::Vote("kim".to_string(), Ballot::Innocent));
+    g = g.process(GameEvent::Abstain("lev".to_string()));
+    tick_phase(g)
+}
+
+/// Test the tie-breaking rules.
+#[test]
+fn test_tie_break() {
+    assert!(tied_verdict(TieBreak::NoExecution).is_alive("olga"));
+    assert!(!tied_verdict(TieBreak::EarliestLeader).is_alive("olga"));
+    assert!(tied_verdict(TieBreak::Stalin).is_alive("olga"));
+    let g = tied_verdict(TieBreak::Random);
+    match g.pending.last().unwrap().event {
+        GameEvent::Tribunal(ref t) => {
+            assert!(t.abstained == vec!["lev".to_string()]);
+            assert!(t.executed != g.is_alive("olga"));
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Submit a night action.
+fn act(g: Game, nick: &str, kind: ActionKind, target: &str) -> Game {
+    g.process(GameEvent::Act(nick.to_string(), kind, target.to_string()))
+}
+
+/// Every private message pending for a nick, in order.
+fn private_to(g: &Game, nick: &str) -> Vec<String> {
+    g.pending
+        .iter()
+        .flat_map(|gr| gr.msg.iter())
+        .filter(|m| match m.recipients {
+            Recipients::Nicks(ref v) => v.iter().any(|n| n == nick),
+            Recipients::Channel(_) => false,
+        })
+        .map(|m| m.content.clone())
+        .collect()
+}
+
+/// Test the Mastermind recruiting a Worker at dawn, and failing on others.
+#[test]
+fn test_recruit() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Mastermind),
+                               ("kim", Role::Stalin),
+                               ("olga", Role::Saboteur)]);
+    g = act(g, "lev", ActionKind::Recruit, "ivan");
+    assert!(g.pending[0].msg[0].content == "That can only be done under cover of night.");
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "olga", ActionKind::Recruit, "ivan");
+    assert!(g.pending[1].msg[0].content == "Your role does not allow that.");
+    g = act(g, "lev", ActionKind::Recruit, "ivan");
+    assert!(g.pending[2].msg[0].content == "You will try to recruit ivan tonight.");
+    g = g.clean_up();
+    g = tick_phase(g);
+    match g.players {
+        Participants::Players(ref v) => {
+            assert!(v[0].role == Role::Saboteur && v[0].real_team == Team::Opposition);
+            assert!(v[0].apparent_team == Team::Opposition && v[0].night_voter);
+            assert!(v[1].game_actions == 0);
+        }
+        _ => unreachable!(),
+    }
+    assert!(private_to(&g, "ivan")[0].starts_with("lev has recruited you"));
+    assert!(private_to(&g, "kim") == vec!["You slept soundly through the night."]);
+    // The attempt is used up.
+    g = g.clean_up();
+    g.phase = Phase::Night(2);
+    g = act(g, "lev", ActionKind::Recruit, "kim");
+    assert!(g.pending[0].msg[0].content == "You have no uses of that ability left.");
+}
+
+/// Test that recruiting Stalin fails and exposes the Mastermind.
+#[test]
+fn test_recruit_exposed() {
+    let mut g = playing_game(&[("lev", Role::Mastermind), ("kim", Role::Stalin)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "lev", ActionKind::Recruit, "kim");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(private_to(&g, "lev") == vec!["kim turns you away. The attempt has failed."]);
+    assert!(private_to(&g, "kim")[0].starts_with("lev tried to recruit you"));
+    match g.players {
+        Participants::Players(ref v) => assert!(v[1].role == Role::Stalin),
+        _ => unreachable!(),
+    }
+}
+
+/// Test the Spy watching a target and learning its visitors at dawn.
+#[test]
+fn test_spy_watch() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Mastermind),
+                               ("kim", Role::Spy),
+                               ("olga", Role::Spy)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "kim", ActionKind::Watch, "ivan");
+    g = act(g, "olga", ActionKind::Watch, "lev");
+    g = act(g, "ivan", ActionKind::Watch, "lev");
+    assert!(g.pending[2].msg[0].content == "Your role does not allow that.");
+    g = act(g, "lev", ActionKind::Recruit, "ivan");
+    // Nothing happens before dawn.
+    assert!(g.visitors(1, "ivan").is_empty());
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(g.visitors(1, "ivan").len() == 2);
+    assert!(private_to(&g, "kim")[0].ends_with("Visitors in the night: lev."));
+    assert!(private_to(&g, "olga")[0] == "Nobody came near lev in the night.");
+    assert!(g.actions.is_empty());
+    // With roles shown, the Spy also learns what the visitor is.
+    g = g.clean_up();
+    g.settings.spy_sees_roles = true;
+    g.phase = Phase::Night(2);
+    g.set_remaining(1);
+    g = act(g, "olga", ActionKind::Watch, "ivan");
+    g = act(g, "kim", ActionKind::Watch, "lev");
+    // A new order replaces the old one.
+    g = act(g, "kim", ActionKind::Watch, "ivan");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(private_to(&g, "olga")[0].ends_with("Visitors in the night: kim (a Spy)."));
+    assert!(private_to(&g, "kim")[0].ends_with("Visitors in the night: olga (a Spy)."));
+}
+
+/// Test the Opposition's single kill a night, and a peaceful dawn without one.
+#[test]
+fn test_night_kill() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Mastermind),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("yuri", Role::Worker),
+                               ("anna", Role::Worker)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "ivan", ActionKind::Kill, "kim");
+    assert!(g.pending[0].msg[0].content == "Your role does not allow that.");
+    g = act(g, "olga", ActionKind::Kill, "kim");
+    assert!(private_to(&g, "lev")[0] == "olga has ordered the opposition to liquidate kim \
+                                          tonight.");
+    // The last order stands.
+    g = act(g, "lev", ActionKind::Kill, "ivan");
+    assert!(g.actions.len() == 1);
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(!g.is_alive("ivan") && g.is_alive("kim"));
+    assert!(private_to(&g, "lev")[0] == "The opposition has dealt with ivan.");
+    assert!(g.phase == Phase::Denunciation(2));
+    g = g.clean_up();
+    g.phase = Phase::Night(2);
+    g.set_remaining(1);
+    g = tick_phase(g);
+    assert!(g.pending[0].msg[0].content.starts_with("The sun rises over a quiet city."));
+}
+
+/// Test that kills are resolved before other actions: the Chekist still
+/// completes a check, but a recruit on the victim fails.
+#[test]
+fn test_night_priority() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Mastermind),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Chekist)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "lev", ActionKind::Recruit, "ivan");
+    g = act(g, "kim", ActionKind::Investigate, "olga");
+    g = act(g, "olga", ActionKind::Kill, "kim");
+    g = act(g, "olga", ActionKind::Kill, "ivan");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(!g.is_alive("ivan") && g.is_alive("kim"));
+    assert!(private_to(&g, "lev")[0].ends_with("The recruitment did not take place."));
+    assert!(private_to(&g, "kim")[0] == "Your investigation is complete: olga is a \
+                                         treasonous opposition member.");
+    match g.players {
+        Participants::Players(ref v) => {
+            assert!(v[1].game_actions == 1);
+            assert!(v[3].game_actions == 7);
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Test the Militya dying in place of the player they guard, and not guarding
+/// the same player two nights in a row.
+#[test]
+fn test_militya_protect() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("olga", Role::Militya),
+                               ("kim", Role::Worker)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "olga", ActionKind::Protect, "ivan");
+    assert!(g.pending[0].msg[0].content == "You will stand guard over ivan tonight.");
+    g = act(g, "lev", ActionKind::Kill, "ivan");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(g.is_alive("ivan") && !g.is_alive("olga"));
+    assert!(private_to(&g, "lev")[0] == "ivan was guarded. The militsioner olga died in \
+                                         their place.");
+    assert!(private_to(&g, "olga")[0].starts_with("The opposition came for ivan."));
+    assert!(private_to(&g, "ivan")[0].ends_with("but olga of the militsia died in your place."));
+    // The channel hears of a death like any other; only the setup's reveal
+    // policy may tell what olga was.
+    assert!(g.pending.iter().any(|gr| {
+        gr.msg.iter().any(|m| m.content == "At dawn, olga is found dead in a ditch.")
+    }));
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("olga", Role::Militya),
+                               ("kim", Role::Worker)]);
+    g.setup.reveal = Reveal::Nothing;
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "olga", ActionKind::Protect, "ivan");
+    g = act(g, "lev", ActionKind::Kill, "ivan");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(!g.is_alive("olga"));
+    assert!(g.pending.iter().all(|gr| {
+        gr.msg.iter().all(|m| match m.recipients {
+            Recipients::Channel(_) => !m.content.to_lowercase().contains("milits"),
+            Recipients::Nicks(_) => true,
+        })
+    }));
+    // A guard on a player nobody attacks passes quietly.
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("olga", Role::Militya)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "olga", ActionKind::Protect, "ivan");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(private_to(&g, "olga") == vec!["Your watch over ivan passed without incident."]);
+    g = g.clean_up();
+    g.phase = Phase::Night(2);
+    g = act(g, "olga", ActionKind::Protect, "ivan");
+    assert!(g.pending[0].msg[0].content.starts_with("You guarded ivan last night."));
+    g = act(g, "olga", ActionKind::Protect, "lev");
+    assert!(g.pending[1].msg[0].content == "You will stand guard over lev tonight.");
+}
+
+/// Test two Militya guarding the same player: the first to take up their
+/// post dies in their place, and the other hears of it.
+#[test]
+fn test_militya_two_guards() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("olga", Role::Militya),
+                               ("kim", Role::Militya),
+                               ("yuri", Role::Worker)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "olga", ActionKind::Protect, "ivan");
+    g = act(g, "kim", ActionKind::Protect, "ivan");
+    g = act(g, "lev", ActionKind::Kill, "ivan");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(g.is_alive("ivan") && !g.is_alive("olga") && g.is_alive("kim"));
+    assert!(private_to(&g, "olga")[0].starts_with("The opposition came for ivan."));
+    assert!(private_to(&g, "kim") ==
+            vec!["The opposition came for ivan, but olga of the militsia stood in their path \
+                  before you and died in their place."]);
+    assert!(private_to(&g, "ivan")[0].ends_with("but olga of the militsia died in your place."));
+}
+
+/// Test that the night visits of one game are forgotten when the next begins.
+#[test]
+fn test_visits_per_game() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Militya)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "kim", ActionKind::Protect, "ivan");
+    g = tick_phase(g.clean_up());
+    assert!(g.visitors(1, "ivan").len() == 1);
+    g.phase = Phase::Inactive;
+    for nick in ["ivan", "olga", "kim", "lev", "yuri"].iter() {
+        g = g.process(GameEvent::Join(nick.to_string()));
+    }
+    while let Phase::Starting = g.phase {
+        g = tick(g.clean_up());
+    }
+    assert!(g.phase == Phase::Denunciation(1));
+    assert!(g.visits.is_empty());
+}
+
+/// Test the Commissar detaining a player: their action fails, they are told
+/// they were held for questioning, and their turn actions come back at dawn.
+#[test]
+fn test_commissar_detain() {
+    let mut g = playing_game(&[("ivan", Role::Commissar),
+                               ("lev", Role::Saboteur),
+                               ("kim", Role::Chekist),
+                               ("olga", Role::Worker)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "lev", ActionKind::Kill, "olga");
+    g = act(g, "kim", ActionKind::Investigate, "lev");
+    g = act(g, "ivan", ActionKind::Block, "lev");
+    assert!(g.pending[2].msg[0].content == "lev will be taken in for questioning tonight.");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(g.is_alive("olga"));
+    assert!(private_to(&g, "lev") ==
+            vec!["You were held for questioning all night, and could do nothing else."]);
+    assert!(private_to(&g, "ivan")[0].starts_with("You kept lev in a cell all night."));
+    assert!(private_to(&g, "kim")[0].starts_with("Your investigation is complete"));
+    assert!(g.visitors(1, "olga").is_empty());
+    match g.players {
+        Participants::Players(ref v) => assert!(v[1].turn_actions == 1),
+        _ => unreachable!(),
+    }
+    // Detaining the Chekist stops the investigation.
+    g = g.clean_up();
+    g.phase = Phase::Night(2);
+    g.set_remaining(1);
+    g = act(g, "kim", ActionKind::Investigate, "olga");
+    g = act(g, "ivan", ActionKind::Block, "kim");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(private_to(&g, "kim")[0].starts_with("You were held for questioning"));
+    match g.players {
+        Participants::Players(ref v) => assert!(v[2].game_actions == 7),
+        _ => unreachable!(),
+    }
+}
+
+/// Test what each reveal policy tells the channel at a death.
+#[test]
+fn test_reveal_on_death() {
+    let reveal = |r: Reveal| {
+        let mut g = playing_game(&[("ivan", Role::Worker), ("olga", Role::Cosmopolitan)]);
+        g.setup.reveal = r;
+        let mut gr = GameReaction::new(&GameEvent::Tick);
+        g.kill("olga", DeathCause::NightKill, &mut gr);
+        gr.msg[1].content.clone()
+    };
+    assert!(reveal(Reveal::Role) ==
+            "The papers of olga show they were a rootless cosmopolitan \
+            Mensch.");
+    assert!(reveal(Reveal::Team) == "The papers of olga show they were a loyal citizen of the \
+                                     Union.");
+    assert!(reveal(Reveal::ApparentTeam) ==
+            "The papers of olga show they were a treasonous opposition member.");
+    assert!(reveal(Reveal::Nothing) == "The papers of olga are sealed until the affair is \
+                                        closed.");
+    let setups = Setup::parse_all("[x]\nplayers.2 = Worker, Saboteur\nreveal = team").unwrap();
+    assert!(setups[0].reveal == Reveal::Team);
+    assert!(Setup::parse_all("[x]\nplayers.2 = Worker, Saboteur\nreveal = all").is_err());
+}
+
+/// Test the game ending when the last of the Opposition is executed, with
+/// every role revealed.
+#[test]
+fn test_game_over() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin)]);
+    g.setup.reveal = Reveal::Nothing;
+    g.phase = Phase::Verdict(1, "olga".to_string());
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    g = g.process(GameEvent::Vote("kim".to_string(), Ballot::Guilty));
+    assert!(g.phase == Phase::Inactive);
+    let end = g.pending.last().unwrap();
+    match end.event {
+        GameEvent::GameOver(t) => assert!(t == Team::Soviet),
+        _ => unreachable!(),
+    }
+    assert!(end.msg[0].content.ends_with("Victory goes to every loyal citizen of the Union!"));
+    assert!(end.msg[1].content == "The archives are opened: ivan (Worker), olga (Saboteur), \
+                                   kim (Stalin).");
+}
+
+/// Test players dying when they leave mid-game or stay idle, and the
+/// Opposition winning once it is half of the living.
+#[test]
+fn test_departure_and_idle() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("olga", Role::Chekist),
+                               ("yuri", Role::Worker)]);
+    g = g.process(GameEvent::Leave("yuri".to_string()));
+    assert!(!g.is_alive("yuri"));
+    assert!(g.pending[0].msg[0].content.starts_with("yuri has fled across the border"));
+    assert!(g.pending[0].msg[1].content.ends_with("they were a glorious Soviet worker."));
+    // Strangers leaving change nothing.
+    g = g.process(GameEvent::Leave("stranger".to_string()));
+    assert!(g.pending.len() == 1);
+    g.settings.idle_days = 1;
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = g.process(GameEvent::Msg("ivan".to_string(), "I am loyal".to_string()));
+    g = g.process(GameEvent::Msg("lev".to_string(), "So am I".to_string()));
+    g = act(g, "olga", ActionKind::Investigate, "lev");
+    g = g.clean_up();
+    g = tick_phase(g);
+    assert!(!g.is_alive("kim") && g.is_alive("ivan") && g.is_alive("olga"));
+    assert!(g.phase == Phase::Denunciation(2));
+    // Only the Saboteur speaks up the next day, and is left alone.
+    g = g.clean_up();
+    g.phase = Phase::Night(2);
+    g.set_remaining(1);
+    g = g.process(GameEvent::Msg("lev".to_string(), "Nearly there".to_string()));
+    g = tick_phase(g);
+    assert!(g.is_alive("lev") && !g.is_alive("ivan") && !g.is_alive("olga"));
+    assert!(g.phase == Phase::Inactive);
+    match g.pending.last().unwrap().event {
+        GameEvent::GameOver(t) => assert!(t == Team::Opposition),
+        _ => unreachable!(),
+    }
+}
+
+/// Test that a player changing nick mid-game keeps their place, their
+/// denunciation and their trial, and that a dead player's nick is not taken over.
+#[test]
+fn test_rename() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("olga", Role::Chekist),
+                               ("yuri", Role::Worker)]);
+    g.phase = Phase::Denunciation(1);
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "lev".to_string()));
+    g = g.clean_up();
+    g = g.process(GameEvent::Rename("lev".to_string(), "lev_away".to_string()));
+    g = g.process(GameEvent::Rename("ivan".to_string(), "vanya".to_string()));
+    assert!(g.pending[0].msg[0].content ==
+            "By decree, comrade lev is henceforth known as lev_away.");
+    assert!(g.is_alive("lev_away") && !g.is_alive("lev") && g.is_alive("vanya"));
+    assert!(g.nominations.get("vanya") == Some(&Some("lev_away".to_string())));
+    g = g.process(GameEvent::Denounce("kim".to_string(), "lev_away".to_string()));
+    assert!(g.phase == Phase::Trial(1, "lev_away".to_string()));
+    g = g.clean_up();
+    g = g.process(GameEvent::Rename("lev_away".to_string(), "lev".to_string()));
+    assert!(g.phase == Phase::Trial(1, "lev".to_string()));
+    // Strangers changing nick change nothing.
+    g = g.clean_up();
+    g = g.process(GameEvent::Rename("stranger".to_string(), "boris".to_string()));
+    assert!(g.pending.is_empty());
+    // Taking a dead player's nick is leaving the game.
+    g = g.process(GameEvent::Leave("yuri".to_string()));
+    g = g.clean_up();
+    g = g.process(GameEvent::Rename("kim".to_string(), "yuri".to_string()));
+    assert!(!g.is_alive("kim") && !g.is_alive("yuri"));
+    assert!(g.pending[0].msg[0].content.starts_with("kim has fled across the border"));
+}
+
+/// Test that nobody dies of inactivity when idle_days is 0.
+#[test]
+fn test_idle_off() {
+    let mut s = Settings::default();
+    assert!(s.set("idle_days", "0").is_ok() && s.idle_days == 0);
+    assert!(s.set("idle_days", "-1").is_err() && s.set("idle_days", "never").is_err());
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("olga", Role::Chekist)]);
+    g.settings = s;
+    for night in 1..4 {
+        g.phase = Phase::Night(night);
+        g.set_remaining(1);
+        g = tick_phase(g.clean_up());
+    }
+    assert!(["ivan", "lev", "kim", "olga"].iter().all(|n| g.is_alive(n)));
+    assert!(g.phase == Phase::Denunciation(4));
+}
+
+/// Test that a trial is called off when the accused leaves, in either of its
+/// phases, and the day goes back to denunciations.
+#[test]
+fn test_accused_departs() {
+    for phase in [Phase::Trial(1, "kim".to_string()), Phase::Verdict(1, "kim".to_string())] {
+        let mut g = playing_game(&[("ivan", Role::Worker),
+                                   ("lev", Role::Saboteur),
+                                   ("kim", Role::Worker),
+                                   ("olga", Role::Chekist),
+                                   ("yuri", Role::Worker)]);
+        g.phase = phase;
+        g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+        g = g.clean_up();
+        g = g.process(GameEvent::Leave("kim".to_string()));
+        assert!(!g.is_alive("kim"));
+        assert!(g.phase == Phase::Denunciation(1));
+        assert!(g.votes.is_empty() && g.nominations.is_empty());
+        let msg = &g.pending[0].msg;
+        assert!(msg[msg.len() - 2].content ==
+                "With the accused kim gone, the Tribunal is adjourned. The Soviet resumes \
+                 its session.");
+        assert!(msg[msg.len() - 1].content.starts_with("Day 1. The Soviet is in session."));
+        // Denunciations go on as on any other day.
+        g = g.clean_up();
+        g = g.process(GameEvent::Denounce("ivan".to_string(), "lev".to_string()));
+        assert!(g.nominations.get("ivan") == Some(&Some("lev".to_string())));
+    }
+    // Anybody else leaving does not stop the trial.
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("lev", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("olga", Role::Chekist),
+                               ("yuri", Role::Worker)]);
+    g.phase = Phase::Verdict(1, "kim".to_string());
+    g = g.process(GameEvent::Leave("yuri".to_string()));
+    assert!(g.phase == Phase::Verdict(1, "kim".to_string()));
+}
+
+/// Test that the dead's denunciations are dropped, and that none outlive the game.
+#[test]
+fn test_dead_denunciations() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("lev", Role::Worker)]);
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "olga".to_string()));
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("ivan", DeathCause::NightKill, &mut gr);
+    assert!(g.nominations.is_empty());
+    // A single denunciation from the living is not enough for a trial.
+    g = g.process(GameEvent::Denounce("kim".to_string(), "olga".to_string()));
+    assert!(g.phase == Phase::Denunciation(1));
+    g = g.process(GameEvent::Leave("olga".to_string()));
+    assert!(g.phase == Phase::Inactive);
+    assert!(g.nominations.is_empty());
+}
+
+/// Test that the dead's ballots are dropped, and that none outlive the game.
+#[test]
+fn test_dead_ballots() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("lev", Role::Worker),
+                               ("yuri", Role::Worker)]);
+    g.phase = Phase::Verdict(1, "olga".to_string());
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("ivan", DeathCause::NightKill, &mut gr);
+    assert!(g.votes.is_empty());
+    g = g.process(GameEvent::Vote("kim".to_string(), Ballot::Innocent));
+    g = g.process(GameEvent::Leave("olga".to_string()));
+    assert!(g.phase == Phase::Inactive);
+    assert!(g.votes.is_empty());
+}
+
+/// Test that the night actions of players who die before dawn are dropped.
+#[test]
+fn test_dead_actions() {
+    let mut g = playing_game(&[("ivan", Role::Chekist),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Worker),
+                               ("lev", Role::Worker)]);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "ivan", ActionKind::Investigate, "olga");
+    g = g.process(GameEvent::Leave("ivan".to_string()));
+    assert!(g.actions.is_empty());
+    g = tick_phase(g.clean_up());
+    assert!(g.visitors(1, "olga").is_empty());
+    assert!(private_to(&g, "ivan").is_empty());
+}
+
+/// Test that a finished game is recorded, and the statistics commands.
+#[test]
+fn test_stats() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin)]);
+    g = g.process(GameEvent::Stats("ivan".to_string(), "ivan".to_string()));
+    assert!(g.pending[0].msg[0].content == "The archive has no file on ivan.");
+    g = g.process(GameEvent::Top("ivan".to_string()));
+    assert!(g.pending[1].msg[0].content == "No affair has been closed yet.");
+    g.phase = Phase::Verdict(1, "olga".to_string());
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    g = g.process(GameEvent::Vote("kim".to_string(), Ballot::Guilty));
+    assert!(g.stats.records.len() == 3);
+    assert!(g.stats.records[0].won && g.stats.records[0].fate == Fate::Survived);
+    assert!(!g.stats.records[1].won && g.stats.records[1].fate == Fate::Executed);
+    // A second game, lost by the Soviets.
+    let stats = std::mem::take(&mut g.stats);
+    g = playing_game(&[("ivan", Role::Chekist), ("olga", Role::Saboteur)]);
+    g.stats = stats;
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = tick_phase(g);
+    assert!(g.stats.next_game() == 3);
+    g = g.clean_up();
+    g = g.process(GameEvent::Stats("kim".to_string(), "IVAN".to_string()));
+    let msgs = g.pending[0].msg.iter().map(|m| m.content.as_str()).collect::<Vec<_>>();
+    assert!(msgs ==
+            vec!["Comrade IVAN, rated 1499: 2 games played, 1 won, 100% survived, shot by the \
+                  tribunal 0 times.",
+                 "As a loyal citizen of the Union: 1 won, 1 lost.",
+                 "Roles held: Chekist (1), Worker (1)."]);
+    g = g.process(GameEvent::Top("kim".to_string()));
+    assert!(g.pending[1].msg[0].content == "Heroes of Socialist Labour: 1. kim, 1 won of 1, \
+                                            2. ivan, 1 won of 2, 3. olga, 1 won of 2.");
+    g = g.process(GameEvent::History("kim".to_string(), "olga".to_string()));
+    assert!(g.pending[2].msg[0].content == "The file on olga: affair #2 as Saboteur, won and \
+                                            survived; affair #1 as Saboteur, lost and was \
+                                            shot.");
+}
+
+/// Test that a Spy who lives to the end is recorded as a winner, whoever
+/// won the game, and a dead one as a loser.
+#[test]
+fn test_stats_spy() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Spy),
+                               ("lev", Role::Spy),
+                               ("yuri", Role::Worker)]);
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("lev", DeathCause::NightKill, &mut gr);
+    g.phase = Phase::Verdict(1, "olga".to_string());
+    for n in ["ivan", "kim", "yuri"].iter() {
+        g = g.process(GameEvent::Vote(n.to_string(), Ballot::Guilty));
+    }
+    assert!(g.phase == Phase::Inactive);
+    let won = |nick: &str| g.stats.records.iter().find(|r| r.nick == nick).unwrap().won;
+    assert!(won("ivan") && !won("olga") && won("kim") && !won("lev"));
+}
+
+/// Test keeping statistics in a file.
+#[test]
+fn test_stats_file() {
+    let path = std::env::temp_dir().join("pravdabot_test.stats");
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let mut g = playing_game(&[("ivan", Role::Worker), ("olga", Role::Saboteur)]);
+    g.stats = Stats::load(path).unwrap();
+    assert!(g.stats.records.is_empty());
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("ivan", DeathCause::NightKill, &mut gr);
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    let _ = tick_phase(g);
+    let stats = Stats::load(path).unwrap();
+    assert!(stats.records.len() == 2);
+    assert!(stats.records[0].nick == "ivan" && stats.records[0].fate == Fate::Died);
+    assert!(stats.records[1].role == Role::Saboteur && stats.records[1].won);
+    assert!(stats.next_game() == 2);
+    std::fs::write(path, "1 ivan Worker Soviet won fled\n").unwrap();
+    assert!(Stats::load(path).is_err());
+    let _ = std::fs::remove_file(path);
+}
+
+/// Test that ratings follow team results, weighted by the teams' strength,
+/// and are replayed from the records file.
+#[test]
+fn test_ratings() {
+    let record = |game: u32, nick: &str, won: bool| {
+        Record {
+            game: game,
+            nick: nick.to_string(),
+            role: Role::Worker,
+            team: Team::Soviet,
+            won: won,
+            fate: Fate::Survived,
+        }
+    };
+    let mut stats = Stats::default();
+    stats.add_game(vec![record(1, "ivan", true), record(1, "olga", false)]).unwrap();
+    assert!(stats.rating("IVAN") == 1516.0 && stats.rating("olga") == 1484.0);
+    assert!(stats.rating("kim") == 1500.0);
+    // Beating a weaker side earns less than the upset does.
+    stats.add_game(vec![record(2, "ivan", true), record(2, "olga", false)]).unwrap();
+    let gain = stats.rating("ivan") - 1516.0;
+    assert!(gain > 0.0 && gain < 16.0);
+    stats.add_game(vec![record(3, "ivan", false), record(3, "olga", true)]).unwrap();
+    assert!(stats.rating("ivan") - 1516.0 - gain < -16.0);
+    // Loading the same records gives the same ratings.
+    let path = std::env::temp_dir().join("pravdabot_ratings.stats");
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let mut saved = Stats::load(path).unwrap();
+    for game in 1..4 {
+        let records = stats.records.iter().filter(|r| r.game == game).cloned().collect();
+        saved.add_game(records).unwrap();
+    }
+    let loaded = Stats::load(path).unwrap();
+    assert!(loaded.rating("ivan") == stats.rating("ivan"));
+    assert!(loaded.rating("olga") == stats.rating("olga"));
+    let _ = std::fs::remove_file(path);
+}
+
+/// Test dealing roles so that the teams' ratings are balanced.
+#[test]
+fn test_balanced_deal() {
+    let mut g = Game::new("#test_channel");
+    g.rng = Rng::new(4);
+    g.setup = Setup::parse_all("[x]\nplayers.4 = Worker, Worker, Saboteur, Saboteur")
+        .unwrap()
+        .remove(0);
+    g.settings.balance = true;
+    g.stats.ratings.insert("ivan".to_string(), 1900.0);
+    g.stats.ratings.insert("olga".to_string(), 1900.0);
+    g.players = Participants::Joiners(vec!["ivan".to_string(),
+                                           "olga".to_string(),
+                                           "kim".to_string(),
+                                           "lev".to_string()]);
+    assert!(g.start());
+    match g.players {
+        Participants::Players(ref v) => {
+            let team = |n: usize| v[n].real_team;
+            assert!(team(0) != team(1) && team(2) != team(3));
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Test log records: their fields, level filtering and file rotation.
+#[test]
+fn test_logging() {
+    let mut g = playing_game(&[("ivan", Role::Worker), ("olga", Role::Saboteur)]);
+    g.phase = Phase::Night(2);
+    let line = logging::format_record(Level::Warn, &g.context(Some("ivan")), "a \"quoted\" word");
+    assert!(line.starts_with("time="));
+    assert!(line.ends_with(" level=WARN channel=#test_channel game=1 phase=Night(2) nick=ivan \
+                            msg=\"a \\\"quoted\\\" word\""));
+    let line = logging::format_record(Level::Info, &Context::default(), "started");
+    assert!(line.ends_with(" level=INFO msg=started"));
+    // Records go to a file, rotated once it is full.
+    let dir = std::env::temp_dir();
+    let path = dir.join("pravdabot_test.log");
+    let path = path.to_str().unwrap();
+    for p in [path.to_string(), format!("{}.1", path), format!("{}.2", path)].iter() {
+        let _ = std::fs::remove_file(p);
+    }
+    let mut opts = HashMap::new();
+    opts.insert("log_stderr".to_string(), "no".to_string());
+    opts.insert("log_file".to_string(), path.to_string());
+    opts.insert("log_max_size".to_string(), "120".to_string());
+    opts.insert("log_keep".to_string(), "1".to_string());
+    let mut l = Logger::from_options(&opts).unwrap();
+    assert!(l.level == Level::Info);
+    l.log(Level::Trace, &Context::nick("ivan"), "dealt role Saboteur");
+    l.log(Level::Info, &Context::nick("ivan"), "first");
+    l.log(Level::Info, &Context::nick("ivan"), "second");
+    l.log(Level::Info, &Context::nick("ivan"), "third");
+    let current = std::fs::read_to_string(path).unwrap();
+    let rotated = std::fs::read_to_string(format!("{}.1", path)).unwrap();
+    assert!(!current.contains("Saboteur") && !rotated.contains("Saboteur"));
+    assert!(rotated.contains("msg=first") && rotated.contains("msg=second"));
+    assert!(current.lines().count() == 1 && current.contains("msg=third"));
+    assert!(std::fs::metadata(format!("{}.2", path)).is_err());
+    for p in [path.to_string(), format!("{}.1", path)].iter() {
+        let _ = std::fs::remove_file(p);
+    }
+    opts.insert("log_level".to_string(), "loud".to_string());
+    assert!(Logger::from_options(&opts).is_err());
+}
+
+/// Test that a snapshot keeps the state of a game in progress.
+#[test]
+fn test_snapshot() {
+    let mut g = playing_game(&[("ivan", Role::Stalin),
+                               ("olga", Role::Saboteur),
+                               ("pyotr", Role::Worker),
+                               ("masha", Role::Worker)]);
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "olga".to_string()));
+    let s = g.snapshot();
+    assert!(s.contains("channel #test_channel\n"));
+    assert!(s.contains("phase Denunciation(1)\n"));
+    assert!(s.contains("player ivan Stalin Soviet alive\n"));
+    assert!(s.contains("player olga Saboteur Opposition alive\n"));
+    assert!(s.contains("denounce ivan olga\n"));
+}
+
+/// Test that the channel is warned as a phase runs out, and that !time tells
+/// how long is left.
+#[test]
+fn test_countdown() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin),
+                               ("lev", Role::Chekist)]);
+    g.set_remaining(45);
+    let mut warnings = Vec::new();
+    while g.phase == Phase::Denunciation(1) {
+        g = tick(g);
+        warnings.extend(g.pending.iter().flat_map(|r| r.msg.iter()).map(|m| m.content.clone()));
+        g = g.clean_up();
+        if g.remaining() == 20 {
+            g = g.process(GameEvent::Time("ivan".to_string()));
+            assert!(private_to(&g, "ivan") == vec!["Time left in this phase: 20 seconds."]);
+            g = g.clean_up();
+        }
+    }
+    assert!(warnings[0] == "Attention, comrades: 30 seconds left.");
+    assert!(warnings[1] == "Attention, comrades: 10 seconds left.");
+    assert!(!warnings.iter().any(|w| w.contains("60 seconds")));
+}
+
+/// Test that only admins can lengthen or shorten a phase.
+#[test]
+fn test_extend() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin),
+                               ("lev", Role::Chekist)]);
+    let mut opts = HashMap::new();
+    opts.insert("admins".to_string(), "Kim!*@kremlin.su, lev!*@*".to_string());
+    g.settings = Settings::from_options(&opts).unwrap();
+    let extend = |nick: &str, secs: i64| {
+        GameEvent::Extend(nick.to_string(), format!("{}!{}@kremlin.su", nick, nick), secs)
+    };
+    g.set_remaining(20);
+    g = g.process(extend("ivan", 60));
+    assert!(g.remaining() == 20);
+    assert!(private_to(&g, "ivan") == vec!["Only the Politburo may touch the clock."]);
+    // An admin's nick is not enough: their host must match too.
+    let impostor = GameEvent::Extend("kim".to_string(), "kim!kim@dacha.ru".to_string(), 60);
+    g = g.clean_up().process(impostor);
+    assert!(g.remaining() == 20);
+    assert!(private_to(&g, "kim") == vec!["Only the Politburo may touch the clock."]);
+    g = g.process(extend("kim", 60));
+    assert!(g.remaining() == 80);
+    assert!(g.pending[1].msg[0].content == "By order of kim, the clock is set: 80 seconds left.");
+    // A minute is now left, so the next warning is the minute one.
+    g = g.process(extend("lev", -19));
+    g = tick(g.clean_up());
+    assert!(g.pending[0].msg[0].content == "Attention, comrades: 60 seconds left.");
+    g = g.process(extend("kim", -500));
+    assert!(g.remaining() == 0);
+    g = tick(g);
+    assert!(g.phase == Phase::Night(1));
+    // Time cannot be pushed past the longest phase, nor before now.
+    g = g.process(extend("kim", i64::MAX));
+    assert!(g.remaining() == u32::MAX as u64);
+    g = g.process(extend("kim", i64::MAX));
+    assert!(g.remaining() == u32::MAX as u64);
+    g = g.process(extend("kim", i64::MIN));
+    assert!(g.remaining() == 0);
+}
+
+/// Test that each player's view holds their own role and what they were told,
+/// and nobody else's role until it is made public.
+#[test]
+fn test_view_for() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin),
+                               ("lev", Role::Chekist)]);
+    g.setup.reveal = Reveal::Team;
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "lev", ActionKind::Investigate, "olga");
+    g = tick_phase(g.clean_up());
+    g = g.process(GameEvent::Denounce("kim".to_string(), "olga".to_string()));
+    let lev = g.view_for("lev");
+    assert!(lev.me.as_ref().map(|p| p.role) == Some(Role::Chekist));
+    assert!(lev.learned.contains(&"Your investigation is complete: olga is a treasonous \
+                                   opposition member."
+        .to_string()));
+    assert!(lev.nominations == vec![("kim".to_string(), Some("olga".to_string()))]);
+    let ivan = g.view_for("ivan");
+    assert!(ivan.me.as_ref().map(|p| p.role) == Some(Role::Worker));
+    assert!(ivan.learned.iter().all(|l| !l.contains("investigation")));
+    for nick in ["ivan", "olga", "kim", "lev", "stranger"].iter() {
+        let v = g.view_for(nick);
+        assert!(v.players.len() == 4);
+        assert!(v.players.iter().all(|s| s.role.is_none() && s.team.is_none()));
+    }
+    // Spectators only see the channel's side.
+    let stranger = g.view_for("stranger");
+    assert!(stranger.me.is_none() && stranger.learned.is_empty());
+    // The dead show what the setup reveals, and keep what they knew.
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("lev", DeathCause::NightKill, &mut gr);
+    let v = g.public_view();
+    assert!(v.players[3].death == Some(DeathCause::NightKill));
+    assert!(v.players[3].team == Some(Team::Soviet) && v.players[3].role.is_none());
+    assert!(g.view_for("lev").learned == lev.learned);
+    // Once the game is over, every role is public.
+    g.phase = Phase::Inactive;
+    assert!(g.public_view().players.iter().all(|s| s.role.is_some()));
+}
+
+/// Test a player and a spectator asking for the state of the game.
+#[test]
+fn test_status() {
+    let mut g = playing_game(&[("ivan", Role::Worker),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin)]);
+    g.set_remaining(45);
+    let mut gr = GameReaction::new(&GameEvent::Tick);
+    g.kill("kim", DeathCause::NightKill, &mut gr);
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "olga".to_string()));
+    g = g.clean_up();
+    g = g.process(GameEvent::Status("ivan".to_string()));
+    assert!(private_to(&g, "ivan") ==
+            vec!["Day 1, the Soviet takes denunciations, 45 seconds left.",
+                 "You, ivan, are a glorious Soviet worker and look like a loyal citizen of the \
+                  Union, while being a loyal citizen of the Union. You have 0 game actions and \
+                  1 turn action left. You are still alive. You are a full voting member of the \
+                  Soviet. You sleep at night.",
+                 "Citizens in good standing: ivan, olga.",
+                 "In the archives: kim (Stalin).",
+                 "Denunciations: ivan → olga."]);
+    g = g.clean_up();
+    g = g.process(GameEvent::Status("stranger".to_string()));
+    let lines = private_to(&g, "stranger");
+    assert!(lines.len() == 4 && !lines.iter().any(|l| l.starts_with("You")));
+}
+
+/// Test the report written at the end of a game, and its announcement.
+#[test]
+fn test_report() {
+    let dir = std::env::temp_dir().join("pravdabot_test_reports");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut g = playing_game(&[("ivan", Role::Chekist),
+                               ("olga", Role::Saboteur),
+                               ("kim", Role::Stalin),
+                               ("lev", Role::Worker)]);
+    g.report_dir = Some(dir.to_str().unwrap().to_string());
+    g = g.process(GameEvent::Will("olga".to_string(), "<b>No regrets</b>".to_string()));
+    g.phase = Phase::Night(1);
+    g.set_remaining(1);
+    g = act(g, "ivan", ActionKind::Investigate, "olga");
+    g = act(g, "olga", ActionKind::Kill, "lev");
+    g = tick_phase(g.clean_up());
+    g = g.process(GameEvent::Denounce("ivan".to_string(), "olga".to_string()));
+    g = g.process(GameEvent::Denounce("kim".to_string(), "olga".to_string()));
+    g = tick_phase(g.clean_up());
+    g = g.process(GameEvent::Vote("ivan".to_string(), Ballot::Guilty));
+    g = g.process(GameEvent::Vote("kim".to_string(), Ballot::Guilty));
+    assert!(g.phase == Phase::Inactive);
+    assert!(g.pending.last().unwrap().msg[0].content ==
+            "The case file is kept in the archives as game-1.html.");
+    let html = std::fs::read_to_string(dir.join("game-1.html")).unwrap();
+    assert!(html.contains("<title>Case file of game #1 in #test_channel</title>"));
+    assert!(html.contains("<li>olga: Saboteur, treasonous opposition member, was shot</li>"));
+    assert!(html.contains("<h3>Day 1</h3>") && html.contains("<h3>Day 2</h3>"));
+    // The vote that ended the game is filed under its day.
+    assert!(!html.contains("The affair opens"));
+    assert!(html.contains("<li>Day 2, olga: shot. Guilty: ivan, kim. Innocent: nobody. \
+                           Abstained: nobody.</li>"));
+    assert!(html.contains("<li>ivan (Chekist): !check olga</li>"));
+    assert!(html.contains("<li>olga (Saboteur): !kill lev</li>"));
+    assert!(html.contains("<h3>ivan</h3>"));
+    assert!(html.contains("olga is a treasonous opposition member."));
+    assert!(html.contains("&lt;b&gt;No regrets&lt;/b&gt;") && !html.contains("<b>"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Nicks that arbitrary events are about. There are enough to start a game.
+const FUZZ_NICKS: [&str; 7] = ["ivan", "olga", "kim", "lev", "masha", "pyotr", "anna"];
+
+/// An arbitrary event by one of the fuzzing nicks, given the game so far.
+/// Ticks are the most common, so that games get to start and move through
+/// their phases. Denunciations favour a few suspects, so that trials are
+/// reached, and the accused sometimes leave in the middle of theirs.
+fn arbitrary_event(rng: &mut Rng, g: &Game) -> GameEvent {
+    let nick = FUZZ_NICKS[rng.below(FUZZ_NICKS.len())].to_string();
+    let other = FUZZ_NICKS[rng.below(FUZZ_NICKS.len())].to_string();
+    let suspect = FUZZ_NICKS[rng.below(2)].to_string();
+    match g.phase {
+        Phase::Trial(_, ref accused) | Phase::Verdict(_, ref accused) if rng.below(8) == 0 => {
+            return GameEvent::Leave(accused.clone());
+        }
+        Phase::Denunciation(_) if rng.below(2) == 0 => return GameEvent::Denounce(nick, suspect),
+        _ => (),
+    }
+    let kinds = [ActionKind::Block,
+                 ActionKind::Protect,
+                 ActionKind::Kill,
+                 ActionKind::Investigate,
+                 ActionKind::Recruit,
+                 ActionKind::Watch];
+    match rng.below(20) {
+        0..=2 => GameEvent::Join(nick),
+        3 => GameEvent::Leave(nick),
+        10 if rng.below(4) == 0 => GameEvent::Rename(nick, other),
+        4 => GameEvent::Denounce(nick, other),
+        5 => GameEvent::Vote(nick, Ballot::Guilty),
+        6 => GameEvent::Vote(nick, Ballot::Innocent),
+        7 => GameEvent::Abstain(nick),
+        8 => GameEvent::Act(nick, kinds[rng.below(kinds.len())], other),
+        9 => {
+            match rng.below(7) {
+                0 => GameEvent::Msg(nick, "hello".to_string()),
+                1 => GameEvent::Will(nick, "avenge me".to_string()),
+                2 => GameEvent::TeamChat(nick, "tonight".to_string()),
+                3 => GameEvent::Time(nick),
+                4 => {
+                    let mask = format!("{0}!{0}@fuzz", nick);
+                    GameEvent::Extend(nick, mask, rng.below(61) as i64 - 30)
+                }
+                5 => GameEvent::Status(nick),
+                _ => GameEvent::Stats(nick, other),
+            }
+        }
+        _ => GameEvent::Tick,
+    }
+}
+
+/// Check what must hold of a game after every event.
+fn check_invariants(g: &Game) -> Result<(), String> {
+    let nicks = match g.players {
+        Participants::Joiners(ref v) => v.clone(),
+        Participants::Players(ref v) => v.iter().map(|p| p.nick.clone()).collect(),
+    };
+    for (i, n) in nicks.iter().enumerate() {
+        if nicks[..i].contains(n) {
+            return Err(format!("{} is in the game twice", n));
+        }
+    }
+    let playing = match g.players {
+        Participants::Players(_) => true,
+        Participants::Joiners(_) => false,
+    };
+    match g.phase {
+        Phase::Starting if playing || nicks.is_empty() => {
+            return Err("starting without joiners".to_string())
+        }
+        Phase::Denunciation(_) |
+        Phase::Trial(_, _) |
+        Phase::Verdict(_, _) |
+        Phase::Night(_) if !playing => return Err("playing without players".to_string()),
+        _ => (),
+    }
+    let alive = |n: &String| g.player(n).is_some_and(|p| p.alive);
+    if let Some(a) = g.actions.iter().find(|a| !alive(&a.actor)) {
+        return Err(format!("{} acts, but is not a living player", a.actor));
+    }
+    if let Some((n, _)) = g.votes.iter().find(|(n, _)| !alive(n)) {
+        return Err(format!("{} votes, but is not a living player", n));
+    }
+    if let Some(n) = g.nominations.keys().find(|n| !alive(n)) {
+        return Err(format!("{} denounces, but is not a living player", n));
+    }
+    match g.phase {
+        Phase::Trial(_, ref accused) |
+        Phase::Verdict(_, ref accused) if !alive(accused) => {
+            return Err(format!("{} is on trial, but is not a living player", accused))
+        }
+        _ => (),
+    }
+    // Nobody may be shown what a living player is while the game is on.
+    if g.phase != Phase::Inactive {
+        for n in FUZZ_NICKS.iter() {
+            let v = g.view_for(n);
+            let known = |s: &&Seen| s.alive && (s.role.is_some() || s.team.is_some());
+            if let Some(s) = v.players.iter().find(known) {
+                return Err(format!("{} is shown what {} is", n, s.nick));
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// Play a sequence of events on a fresh game, checking the invariants after
+/// each. Returns the step that went wrong and how, if any did.
+fn run_events(events: &[GameEvent]) -> Result<(), (usize, String)> {
+    let mut g = fuzz_game();
+    for (i, e) in events.iter().enumerate() {
+        g = fuzz_step(g, e).map_err(|e| (i, e))?;
+    }
+    return Ok(());
+}
+
+/// A game for arbitrary events to be played on, with short phases.
+fn fuzz_game() -> Game {
+    let mut g = Game::new("#test_channel");
+    g.rng = Rng::new(1);
+    g.clock = Clock::manual();
+    let mut opts = HashMap::new();
+    for key in ["start_time", "denunciation_time", "trial_time", "verdict_time", "night_time"]
+        .iter() {
+        opts.insert(key.to_string(), "3".to_string());
+    }
+    opts.insert("admins".to_string(), "ivan!*@*".to_string());
+    g.settings = Settings::from_options(&opts).unwrap();
+    return g;
+}
+
+/// Play one event on a game, checking the invariants afterwards.
+fn fuzz_step(mut g: Game, e: &GameEvent) -> Result<Game, String> {
+    if let GameEvent::Tick = *e {
+        g.clock.advance(Duration::from_secs(1));
+    }
+    let e = e.clone();
+    let step = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+        g.process(e).clean_up()
+    }));
+    let g = step.map_err(|_| "panicked".to_string())?;
+    check_invariants(&g)?;
+    return Ok(g);
+}
+
+/// Shrink a failing sequence of events: drop runs of events, then single
+/// ones, for as long as what is left still fails.
+fn shrink<F: Fn(&[GameEvent]) -> bool>(mut events: Vec<GameEvent>, fails: &F) -> Vec<GameEvent> {
+    let mut chunk = events.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start < events.len() {
+            let end = (start + chunk).min(events.len());
+            let mut smaller = events[..start].to_vec();
+            smaller.extend_from_slice(&events[end..]);
+            match fails(&smaller) {
+                true => events = smaller,
+                false => start += chunk,
+            }
+        }
+        chunk /= 2;
+    }
+    return events;
+}
+
+/// Test that shrinking keeps only the events a failure needs.
+#[test]
+fn test_shrink() {
+    let mut rng = Rng::new(7);
+    let g = fuzz_game();
+    let mut events = (0..200).map(|_| arbitrary_event(&mut rng, &g)).collect::<Vec<_>>();
+    events.insert(50, GameEvent::Join("boris".to_string()));
+    events.insert(150, GameEvent::Leave("boris".to_string()));
+    // Fails when boris joins, then leaves.
+    let fails = |events: &[GameEvent]| {
+        let join = events.iter().position(|e| match *e {
+            GameEvent::Join(ref n) => n == "boris",
+            _ => false,
+        });
+        let leave = events.iter().rposition(|e| match *e {
+            GameEvent::Leave(ref n) => n == "boris",
+            _ => false,
+        });
+        match (join, leave) {
+            (Some(j), Some(l)) => j < l,
+            _ => false,
+        }
+    };
+    let minimal = shrink(events, &fails);
+    assert!(minimal.len() == 2);
+    assert!(fails(&minimal));
+}
+
+/// Test that arbitrary sequences of events never panic or break the game's
+/// invariants. A failure is shrunk to a minimal sequence that reproduces it.
+#[test]
+fn test_arbitrary_events() {
+    for seed in 1..201 {
+        let mut rng = Rng::new(seed);
+        let mut g = fuzz_game();
+        let mut events = Vec::new();
+        for _ in 0..400 {
+            let e = arbitrary_event(&mut rng, &g);
+            events.push(e.clone());
+            g = match fuzz_step(g, &e) {
+                Ok(g) => g,
+                Err(_) => break,
+            };
+        }
+        if let Err((_, e)) = run_events(&events) {
+            let minimal = shrink(events, &|events| run_events(events).is_err());
+            let reason = run_events(&minimal).err().map(|(_, e)| e).unwrap_or(e);
+            panic!("seed {}: {} after {:?}", seed, reason, minimal);
+        }
+    }
+}
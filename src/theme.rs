@@ -0,0 +1,206 @@
+//! Module: theme.
+//! This module contains the message catalog: every player-facing string is
+//! looked up here by name and filled in from a template, in a given locale.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+/// Directory where custom theme files are looked up.
+const THEME_DIR: &str = "themes";
+
+/// Built-in theme: the 1930s Soviet pack. This is also the default.
+const SOVIET: &str = include_str!("../themes/soviet.theme");
+
+/// Built-in theme: a plain mafia pack.
+const NEUTRAL: &str = include_str!("../themes/neutral.theme");
+
+/// Built-in translations of the above.
+const SOVIET_RU: &str = include_str!("../themes/soviet.ru.theme");
+const NEUTRAL_RU: &str = include_str!("../themes/neutral.ru.theme");
+
+/// Locale every theme is written in first, and that translations fall back to.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// A named catalog of message templates, in one locale.
+pub struct Theme {
+    pub name: String,
+    pub locale: String,
+    templates: HashMap<String, String>,
+}
+
+impl Theme {
+    /// Parse a theme out of the contents of a theme file.
+    /// Each line is "key = template"; blank lines and lines starting with # are skipped.
+    /// Keys missing from the file fall back to the Soviet pack.
+    pub fn parse(name: &str, contents: &str) -> Result<Theme, String> {
+        let base = match name {
+            "soviet" => {
+                Theme {
+                    name: name.to_string(),
+                    locale: DEFAULT_LOCALE.to_string(),
+                    templates: HashMap::new(),
+                }
+            }
+            _ => {
+                Theme {
+                    name: name.to_string(),
+                    ..Theme::default()
+                }
+            }
+        };
+        base.extend(contents)
+    }
+
+    /// Add the templates in the contents of a theme file, replacing existing ones.
+    fn extend(mut self, contents: &str) -> Result<Theme, String> {
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.find('=') {
+                Some(i) => {
+                    let key = line[..i].trim();
+                    if key.is_empty() {
+                        return Err(format!("{}: line {} has no key.", self.name, n + 1));
+                    }
+                    self.templates.insert(key.to_string(), line[i + 1..].trim().to_string());
+                }
+                None => {
+                    return Err(format!("{}: line {} is not a template.", self.name, n + 1))
+                }
+            }
+        }
+        return Ok(self);
+    }
+
+    /// Load a theme by name and locale, either built in or from the themes directory.
+    /// The English pack is themes/<name>.theme and translations are
+    /// themes/<name>.<locale>.theme; keys a translation lacks stay in English.
+    pub fn load(name: &str, locale: &str) -> Result<Theme, String> {
+        let base = match name {
+            "soviet" => Theme::parse(name, SOVIET),
+            "neutral" => Theme::parse(name, NEUTRAL),
+            _ => read_theme_file(name, None).and_then(|c| Theme::parse(name, &c)),
+        };
+        if locale == DEFAULT_LOCALE {
+            return base;
+        }
+        let translation = match (name, locale) {
+            ("soviet", "ru") => Ok(SOVIET_RU.to_string()),
+            ("neutral", "ru") => Ok(NEUTRAL_RU.to_string()),
+            _ => read_theme_file(name, Some(locale)),
+        };
+        let contents = translation?;
+        let mut t = base?;
+        t.locale = locale.to_string();
+        t.extend(&contents)
+    }
+
+    /// Get a template without filling it in. Unknown keys give back the key itself.
+    pub fn get(&self, key: &str) -> String {
+        match self.templates.get(key) {
+            Some(s) => s.clone(),
+            None => key.to_string(),
+        }
+    }
+
+    /// Fill in a template, replacing every {name} with its value.
+    /// Values are inserted as they are, so nicks containing braces are safe.
+    pub fn render(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let template = self.get(key);
+        let mut result = String::new();
+        let mut rest = template.as_str();
+        while let Some(open) = rest.find('{') {
+            result.push_str(&rest[..open]);
+            rest = &rest[open..];
+            let value = rest.find('}').and_then(|close| {
+                args.iter()
+                    .find(|&&(k, _)| k == &rest[1..close])
+                    .map(|&(_, v)| (close, v))
+            });
+            match value {
+                Some((close, v)) => {
+                    result.push_str(v);
+                    rest = &rest[close + 1..];
+                }
+                None => {
+                    result.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        result.push_str(rest);
+        return result;
+    }
+
+    /// Fill in the form of a counted template that fits n, as in "count.game_actions".
+    /// The count itself is available to the template as {n}.
+    pub fn count(&self, key: &str, n: u32) -> String {
+        let n_str = n.to_string();
+        let form = format!("{}.{}", key, plural_category(&self.locale, n));
+        if self.templates.contains_key(&form) {
+            return self.render(&form, &[("n", &n_str)]);
+        }
+        // Fall back to the most general forms the English pack has.
+        for cat in ["other", "one"].iter() {
+            let form = format!("{}.{}", key, cat);
+            if self.templates.contains_key(&form) {
+                return self.render(&form, &[("n", &n_str)]);
+            }
+        }
+        return format!("{} {}", n, key);
+    }
+}
+
+/// Plural category of a count in a locale, following the CLDR names.
+pub fn plural_category(locale: &str, n: u32) -> &'static str {
+    match locale {
+        "ru" => {
+            if n % 10 == 1 && n % 100 != 11 {
+                "one"
+            } else if n % 10 >= 2 && n % 10 <= 4 && (n % 100 < 12 || n % 100 > 14) {
+                "few"
+            } else {
+                "many"
+            }
+        }
+        _ => {
+            match n {
+                1 => "one",
+                _ => "other",
+            }
+        }
+    }
+}
+
+/// Read a theme file from the themes directory.
+fn read_theme_file(name: &str, locale: Option<&str>) -> Result<String, String> {
+    // Names come from IRC, so keep them from walking the filesystem.
+    let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !valid(name) || !locale.is_none_or(valid) {
+        return Err(format!("Invalid theme name: {} {}", name, locale.unwrap_or("")));
+    }
+    let path = match locale {
+        Some(l) => format!("{}/{}.{}.theme", THEME_DIR, name, l),
+        None => format!("{}/{}.theme", THEME_DIR, name),
+    };
+    let mut contents = String::new();
+    match File::open(&path) {
+        Ok(mut f) => {
+            match f.read_to_string(&mut contents) {
+                Ok(_) => Ok(contents),
+                Err(e) => Err(format!("{}: {}", path, e)),
+            }
+        }
+        Err(e) => Err(format!("{}: {}", path, e)),
+    }
+}
+
+impl Default for Theme {
+    /// The Soviet pack.
+    fn default() -> Theme {
+        Theme::parse("soviet", SOVIET).unwrap()
+    }
+}
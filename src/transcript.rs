@@ -0,0 +1,99 @@
+//! Module: transcript.
+//! This module contains the transcript the bot keeps while it hosts games:
+//! the seed its games are dealt from, then everything it hears from the
+//! server, tick by tick, as lines of play. Replaying the transcript plays the
+//! same games again. It holds every secret, night actions included.
+
+use std::fs::File;
+use std::io::Write;
+use irc::client::prelude::Message;
+use irc::client::data::command::Command;
+use cli::Line;
+use logging::{self, Context};
+
+/// A transcript being written.
+pub struct Transcript {
+    out: Option<Box<dyn Write + Send>>,
+    /// Ticks heard since the last line written.
+    ticks: u32,
+}
+
+impl Transcript {
+    /// A transcript that is not kept.
+    pub fn none() -> Transcript {
+        Transcript {
+            out: None,
+            ticks: 0,
+        }
+    }
+
+    /// Start a transcript, writing it to a file, and record the seed the
+    /// games are dealt from.
+    pub fn create(path: &str, seed: u64) -> Result<Transcript, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut t = Transcript {
+            out: Some(Box::new(file)),
+            ticks: 0,
+        };
+        t.write(&Line::Seed(seed));
+        return Ok(t);
+    }
+
+    /// Record a message from the server, if it is one the game hears.
+    pub fn hear(&mut self, msg: &Message) {
+        if let Some(line) = heard(msg) {
+            self.write(&line);
+        }
+    }
+
+    /// Record a tick.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// Write out the ticks not written yet.
+    pub fn flush(&mut self) {
+        if self.ticks > 0 {
+            let ticks = Line::Tick(self.ticks);
+            self.ticks = 0;
+            self.write(&ticks);
+        }
+    }
+
+    /// Write a line, after the ticks heard before it. A transcript that
+    /// cannot be written is given up on.
+    fn write(&mut self, line: &Line) {
+        match *line {
+            Line::Tick(_) => (),
+            _ => self.flush(),
+        }
+        let failed = match self.out {
+            Some(ref mut out) => writeln!(out, "{}", line).and_then(|_| out.flush()).err(),
+            None => None,
+        };
+        if let Some(e) = failed {
+            logging::error(&Context::default(), &format!("cannot write transcript: {}", e));
+            self.out = None;
+        }
+    }
+}
+
+/// The line of play a message from the server is, if the game hears it.
+/// What users say is kept with their full nick!user@host, which admin
+/// commands are checked against.
+pub fn heard(msg: &Message) -> Option<Line> {
+    let nick = msg.source_nickname()?.to_string();
+    match msg.command {
+        Command::PRIVMSG(ref target, ref text) => {
+            let source = msg.prefix.clone()?;
+            match target.starts_with('#') {
+                true => Some(Line::Say(source, text.clone())),
+                false => Some(Line::Tell(source, text.clone())),
+            }
+        }
+        Command::NICK(ref new) => Some(Line::Rename(nick, new.clone())),
+        Command::QUIT(_) |
+        Command::PART(_, _) => Some(Line::Leave(nick)),
+        _ => None,
+    }
+}
@@ -0,0 +1,200 @@
+//! Module: view.
+//! This module contains what one person is allowed to know of a game: the
+//! public state of play, and for players their own role and what they have
+//! been told in private. Anything shown to a player or a spectator should be
+//! built from a View rather than from the Game, so that no role leaks out.
+
+use model::{Ballot, DeathCause, Game, Participants, Phase, Player, Recipients, Role, Team};
+use setup::Reveal;
+use theme::Theme;
+
+/// A player as the channel knows them.
+#[derive(Clone, Debug)]
+pub struct Seen {
+    pub nick: String,
+    pub alive: bool,
+    pub death: Option<DeathCause>,
+    /// Their role, once it has been made public.
+    pub role: Option<Role>,
+    /// Their team, when the setup reveals that instead of the role.
+    pub team: Option<Team>,
+}
+
+/// A game as seen by one person.
+#[derive(Clone, Debug)]
+pub struct View {
+    pub channel: String,
+    pub phase: Phase,
+    /// Seconds left in the phase.
+    pub remaining: u64,
+    /// The viewer, if they play in the game.
+    pub me: Option<Player>,
+    /// Everything the game told the viewer privately since it began, oldest first.
+    pub learned: Vec<String>,
+    /// Everyone who joined or plays, in order.
+    pub players: Vec<Seen>,
+    /// Who denounced whom today; None for a vote to execute nobody.
+    pub nominations: Vec<(String, Option<String>)>,
+    /// Ballots cast on the accused.
+    pub votes: Vec<(String, Ballot)>,
+}
+
+impl Game {
+    /// The game as a nick may know it. Players see their own role and what
+    /// they have been told; anybody else gets the spectators' view.
+    pub fn view_for(&self, nick: &str) -> View {
+        let mut v = self.public_view();
+        if let Some(p) = self.player(nick) {
+            v.me = Some(p.clone());
+            v.learned = self.told(nick);
+        }
+        return v;
+    }
+
+    /// The game as spectators know it: only what was said in the channel.
+    pub fn public_view(&self) -> View {
+        // Once the game is over, the archives are opened on every role.
+        let over = self.phase == Phase::Inactive;
+        let players = match self.players {
+            Participants::Joiners(ref v) => {
+                v.iter()
+                    .map(|n| {
+                        Seen {
+                            nick: n.clone(),
+                            alive: true,
+                            death: None,
+                            role: None,
+                            team: None,
+                        }
+                    })
+                    .collect()
+            }
+            Participants::Players(ref v) => {
+                v.iter()
+                    .map(|p| {
+                        let reveal = match (over, p.alive) {
+                            (true, _) => Reveal::Role,
+                            (false, true) => Reveal::Nothing,
+                            (false, false) => self.setup.reveal,
+                        };
+                        Seen {
+                            nick: p.nick.clone(),
+                            alive: p.alive,
+                            death: p.death,
+                            role: match reveal {
+                                Reveal::Role => Some(p.role),
+                                _ => None,
+                            },
+                            team: match reveal {
+                                Reveal::Team => Some(p.real_team),
+                                Reveal::ApparentTeam => Some(p.apparent_team),
+                                _ => None,
+                            },
+                        }
+                    })
+                    .collect()
+            }
+        };
+        let mut nominations = self.nominations
+            .iter()
+            .map(|(n, t)| (n.clone(), t.clone()))
+            .collect::<Vec<_>>();
+        nominations.sort();
+        let v = View {
+            channel: self.channel.clone(),
+            phase: self.phase.clone(),
+            remaining: self.remaining(),
+            me: None,
+            learned: Vec::new(),
+            players: players,
+            nominations: nominations,
+            votes: self.votes.clone(),
+        };
+        return v;
+    }
+
+    /// Private messages sent to a nick since the current game began.
+    fn told(&self, nick: &str) -> Vec<String> {
+        self.game_log()
+            .into_iter()
+            .flat_map(|gr| gr.msg.iter())
+            .filter(|m| match m.recipients {
+                Recipients::Nicks(ref v) => v.iter().any(|n| n == nick),
+                Recipients::Channel(_) => false,
+            })
+            .map(|m| m.content.clone())
+            .collect()
+    }
+}
+
+impl View {
+    /// The view as a few lines of text, worded by the given theme.
+    pub fn describe(&self, t: &Theme) -> Vec<String> {
+        let phase = match self.phase {
+            Phase::Inactive => return vec![t.get("time.none")],
+            Phase::Starting => t.get("status.starting"),
+            Phase::Denunciation(d) => t.render("status.denunciation", &[("day", &d.to_string())]),
+            Phase::Trial(d, ref accused) => {
+                t.render("status.trial", &[("day", &d.to_string()), ("accused", accused)])
+            }
+            Phase::Verdict(d, ref accused) => {
+                t.render("status.verdict", &[("day", &d.to_string()), ("accused", accused)])
+            }
+            Phase::Night(d) => t.render("status.night", &[("day", &d.to_string())]),
+        };
+        let time = t.count("count.seconds", self.remaining as u32);
+        let mut lines = vec![t.render("status.phase", &[("phase", &phase), ("time", &time)])];
+        if let Some(ref p) = self.me {
+            lines.push(p.to_string(t));
+        }
+        let alive = self.players
+            .iter()
+            .filter(|s| s.alive)
+            .map(|s| s.nick.clone())
+            .collect::<Vec<_>>();
+        if !alive.is_empty() {
+            lines.push(t.render("status.alive", &[("nicks", &alive.join(", "))]));
+        }
+        let dead = self.players
+            .iter()
+            .filter(|s| !s.alive)
+            .map(|s| {
+                let known = match (s.role, s.team) {
+                    (Some(r), _) => t.get(r.name_key()),
+                    (None, Some(team)) => t.get(team.key()),
+                    (None, None) => return s.nick.clone(),
+                };
+                t.render("status.dead_entry", &[("nick", &s.nick), ("known", &known)])
+            })
+            .collect::<Vec<_>>();
+        if !dead.is_empty() {
+            lines.push(t.render("status.dead", &[("nicks", &dead.join(", "))]));
+        }
+        if !self.nominations.is_empty() {
+            let nobody = t.get("verdict.nobody");
+            let entries = self.nominations
+                .iter()
+                .map(|(n, accused)| {
+                    t.render("status.nomination",
+                             &[("nick", n), ("accused", accused.as_ref().unwrap_or(&nobody))])
+                })
+                .collect::<Vec<_>>();
+            lines.push(t.render("status.nominations", &[("nominations", &entries.join(", "))]));
+        }
+        if !self.votes.is_empty() {
+            let entries = self.votes
+                .iter()
+                .map(|&(ref n, ballot)| {
+                    let vote = t.get(match ballot {
+                        Ballot::Guilty => "vote.guilty",
+                        Ballot::Innocent => "vote.innocent",
+                        Ballot::Abstain => "vote.abstain",
+                    });
+                    t.render("status.vote", &[("nick", n), ("vote", &vote)])
+                })
+                .collect::<Vec<_>>();
+            lines.push(t.render("status.votes", &[("votes", &entries.join(", "))]));
+        }
+        return lines;
+    }
+}